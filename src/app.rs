@@ -1,192 +1,658 @@
-use hyprland::event_listener::Event as HyprlandEvent;
+use std::collections::HashMap;
 use std::error;
 use std::io;
-use tokio::sync::mpsc;
-use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+use std::time::Duration;
+use tokio::time::MissedTickBehavior;
 
-use ratatui::{Terminal, backend::CrosstermBackend};
+use chrono::{DateTime, FixedOffset, Local, Months, NaiveDate};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent, MouseEventKind};
+use ratatui::{
+    Terminal,
+    backend::{CrosstermBackend, TestBackend},
+    layout::{Position, Rect},
+};
 
 use crate::batterywidget::BatteryState;
-use crate::event::{Event, EventHandler};
+use crate::clockwidget::Clock;
+use crate::config::Config;
+use crate::event::{Event, EventHandler, Timer};
+use crate::format::Formats;
 use crate::hyprlandwidget::HyprlandState;
-use crate::networkwidget::NetworkState;
-use crate::pipemon::PipeWireEvent;
+use crate::hyprmon::HyprlandEvent;
+use crate::icons::Icons;
+use crate::networkwidget::{NetworkEvent, NetworkState};
+use crate::overlay::{Overlay, Popup};
+use crate::pipemon::{PipeWireCommand, PipeWireEvent};
 use crate::pipewirewidget::PipewireState;
+use crate::record::{self, Recorder};
+use crate::theme::Theme;
 use crate::tui::Tui;
-
-use std::sync::Arc;
-
-use crate::network::nl80211_stream::Event as NetEvent;
+use crate::ui;
 
 pub type AppResult<T> = std::result::Result<T, Box<dyn error::Error>>;
 
 #[derive(Debug, Clone)]
 pub enum Action {
-    Render,
     UpdateHyprlandState(HyprlandEvent),
+    HyprlandConnected(Option<Box<HyprlandState>>),
+    HyprlandDisconnected,
     UpdatePipeWireState(PipeWireEvent),
-    UpdateNetworkState(NetEvent),
-    Tick,
+    UpdateNetworkState(NetworkEvent),
+    UpdateBatteryState(BatteryState),
+    UpdateBluetoothBatteries(HashMap<String, u8>),
+    Key(KeyEvent),
+    Mouse(MouseEvent),
+    Resize,
+    Tick(Timer),
     None,
 }
 
+/// How fast an overflowing window title scrolls.
+const MARQUEE_INTERVAL: Duration = Duration::from_millis(300);
+
+/// Where the app's events come from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Source {
+    /// Hyprland, PipeWire, netlink and the power supply.
+    Live,
+    /// A recording, nothing is read from the system.
+    Replay,
+    /// The synthetic sources of `kbar --demo`.
+    Demo,
+}
+
 pub struct App {
     pub running: bool,
     pub hyprland_state: HyprlandState,
     pub battery_state: BatteryState,
     pub pipwire_state: PipewireState,
     pub network_state: NetworkState,
-    action_tx: UnboundedSender<Action>,
-    action_rx: UnboundedReceiver<Action>,
+    pub theme: Theme,
+    pub clock: Clock,
+    pub icons: Icons,
+    pub formats: Formats,
+    /// The open popup, it takes the keyboard and clicks on it.
+    pub overlay: Option<Overlay>,
+    /// Where the volume widget was last drawn, clicking it opens the mixer.
+    pub volume_area: Rect,
+    /// Where the clock was last drawn, clicking it opens the calendar.
+    pub clock_area: Rect,
+    /// A day of the month the calendar shows.
+    pub calendar: NaiveDate,
+    /// Whether the window title is scrolling, which keeps the marquee timer running.
+    pub title_scrolling: bool,
+    /// Shown by the clock instead of the current time when set, for snapshots.
+    pub now: Option<DateTime<FixedOffset>>,
+    /// What the clock showed at its last tick, ticks within the same minute change nothing.
+    clock_text: String,
+    /// Set when any state changed since the last draw.
+    dirty: bool,
+    source: Source,
+    recorder: Option<Recorder>,
+    pipewire_tx: pipewire::channel::Sender<PipeWireCommand>,
+    pipewire_rx: Option<pipewire::channel::Receiver<PipeWireCommand>>,
+}
+
+impl Default for App {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl App {
-    pub async fn new() -> Self {
-        let (action_tx, action_rx) = mpsc::unbounded_channel::<Action>();
+    pub fn new() -> Self {
+        Self {
+            source: Source::Live,
+            ..Self::offline()
+        }
+    }
+
+    /// An app that starts out empty and never connects to Hyprland, PipeWire or netlink.
+    pub fn offline() -> Self {
+        let (pipewire_tx, pipewire_rx) = pipewire::channel::channel::<PipeWireCommand>();
         Self {
             running: true,
-            hyprland_state: HyprlandState::new(),
+            hyprland_state: HyprlandState::default(),
             battery_state: BatteryState::new(),
             pipwire_state: PipewireState::new(),
-            network_state: NetworkState::new().await,
-            action_tx: action_tx.clone(),
-            action_rx,
+            network_state: NetworkState::new(),
+            theme: Theme::default(),
+            clock: Clock::default(),
+            icons: Icons::default(),
+            formats: Formats::default(),
+            overlay: None,
+            volume_area: Rect::default(),
+            clock_area: Rect::default(),
+            calendar: NaiveDate::default(),
+            title_scrolling: false,
+            now: None,
+            clock_text: String::new(),
+            dirty: true,
+            source: Source::Replay,
+            recorder: None,
+            pipewire_tx,
+            pipewire_rx: Some(pipewire_rx),
+        }
+    }
+
+    /// An app fed by synthetic sources, for working on the bar without the services it shows.
+    pub fn demo() -> Self {
+        Self {
+            source: Source::Demo,
+            ..Self::offline()
         }
     }
 
+    /// Applies the settings from the config file, and `$KBAR_MONITOR` over its monitor.
+    pub fn configure(&mut self, config: Config) {
+        self.hyprland_state.monitor = std::env::var("KBAR_MONITOR").ok().or(config.monitor);
+        self.theme = config.theme;
+        self.clock = config.clock;
+        self.icons = config.icons;
+        self.formats = config.format;
+    }
+
+    /// Writes every event `run` receives to `path`.
+    pub fn record_to(&mut self, path: &str) -> AppResult<()> {
+        self.recorder = Some(Recorder::create(path)?);
+        Ok(())
+    }
+
+    /// Feeds a recording through `update` and prints the bar as it looks afterwards.
+    ///
+    /// The width comes from `COLUMNS` and should match the recording terminal.
+    pub fn replay(&mut self, path: &str) -> AppResult<()> {
+        let width = std::env::var("COLUMNS")
+            .ok()
+            .and_then(|columns| columns.parse().ok())
+            .unwrap_or(160);
+        let terminal = self.replayed(path, width)?;
+        let buffer = terminal.backend().buffer();
+        let bar: String = (0..buffer.area.width)
+            .map(|x| buffer[(x, 0)].symbol())
+            .collect();
+        println!("{}", bar.trim_end());
+        Ok(())
+    }
+
+    /// Feeds a recording through `update`, returning the bar drawn `width` columns wide.
+    ///
+    /// The bar is drawn after every event, like `run` does, so clicks land on the areas they hit
+    /// when recorded. The clock shows the time each event arrived.
+    fn replayed(&mut self, path: &str, width: u16) -> AppResult<Terminal<TestBackend>> {
+        let mut terminal = Terminal::new(TestBackend::new(width, 1))?;
+        let recording = record::read(path)?;
+        for recorded in &recording.events {
+            self.now = Some(recording.time(recorded));
+            self.update(Self::action(recorded.event.clone()));
+            terminal.draw(|frame| ui::render(self, frame))?;
+            if !self.running {
+                break;
+            }
+        }
+        Ok(terminal)
+    }
+
     pub async fn run(&mut self) -> AppResult<()> {
         let backend = CrosstermBackend::new(io::stdout());
         let terminal = Terminal::new(backend).expect("Failed to create backend");
-        let multicast = match Arc::try_unwrap(self.network_state.multicast.take().unwrap()) {
-            Ok(val) => val,
-            Err(_) => panic!("multicast still has other references"),
+        let events = match self.source {
+            Source::Demo => EventHandler::demo(self.clock.tick()),
+            _ => EventHandler::new(self.pipewire_rx.take().unwrap(), self.clock.tick()),
         };
-        let events = EventHandler::new(100, self.network_state.socket.clone(), multicast);
         let mut tui = Tui::new(terminal, events);
         tui.init().expect("Failed to inialize");
+        let mut marquee = tokio::time::interval(MARQUEE_INTERVAL);
+        marquee.set_missed_tick_behavior(MissedTickBehavior::Delay);
         while self.running {
-            match tui.events.next().await? {
-                Event::Tick => self.action_tx.send(Action::Tick)?,
-                Event::Render => self.action_tx.send(Action::Render)?,
-                Event::Mouse(_) => self.action_tx.send(Action::None)?,
-                Event::Resize(_, _) => self.action_tx.send(Action::None)?,
-                Event::UpdateHyprlandState(event) => {
-                    self.action_tx.send(Action::UpdateHyprlandState(event))?
-                }
-                Event::UpdatePipeWireState(event) => {
-                    self.action_tx.send(Action::UpdatePipeWireState(event))?
-                }
-                Event::UpdateNetworkState(event) => {
-                    self.action_tx.send(Action::UpdateNetworkState(event))?
-                }
+            let event = tokio::select! {
+                event = tui.events.next() => event?,
+                _ = marquee.tick(), if self.title_scrolling => Event::Tick(Timer::Marquee),
+            };
+            self.handle(event);
+            // Take whatever else is already queued so a burst of events is drawn once
+            while let Some(event) = tui.events.try_next() {
+                self.handle(event);
             }
-
-            while let Ok(action) = self.action_rx.try_recv() {
-                let _ = self.update(action.clone()).await;
-                if let Action::Render = action {
-                    tui.draw(self)?;
-                };
+            if self.dirty {
+                tui.draw(self)?;
+                tui.fit(self.rows())?;
+                self.dirty = false;
             }
         }
+        let _ = self.pipewire_tx.send(PipeWireCommand::Quit);
+        tui.events.join_pipewire();
+        tui.exit()?;
+        let metrics = tui.events.metrics();
+        if metrics.coalesced() > 0 || metrics.dropped() > 0 {
+            eprintln!(
+                "kbar: {} events coalesced, {} dropped",
+                metrics.coalesced(),
+                metrics.dropped()
+            );
+        }
         Ok(())
     }
 
-    async fn update(&mut self, action: Action) {
-        match action {
-            Action::UpdateHyprlandState(hyprland_event) => match hyprland_event {
-                HyprlandEvent::WorkspaceChanged(workspace_event_data) => {
-                    let mut i = 0;
-                    for workspace in &self.hyprland_state.workspaces {
-                        if workspace_event_data.id == workspace.0 {
-                            self.hyprland_state.activeworkspaceindex = i;
-                            break;
-                        }
-                        i += 1;
-                    }
-                }
-                HyprlandEvent::WorkspaceDeleted(workspace_event_data) => {
-                    match self
-                        .hyprland_state
-                        .workspaces
-                        .binary_search_by_key(&workspace_event_data.id, |&(a, _)| a)
-                    {
-                        Ok(pos) => {
-                            self.hyprland_state.workspaces.remove(pos);
-                            if pos < self.hyprland_state.activeworkspaceindex {
-                                self.hyprland_state.activeworkspaceindex -= 1;
-                            }
-                        }
-                        Err(_pos) => {}
-                    }
-                }
-                HyprlandEvent::WorkspaceAdded(workspace_event_data) => {
-                    match self
-                        .hyprland_state
-                        .workspaces
-                        .binary_search_by_key(&workspace_event_data.id, |&(a, _)| a)
-                    {
-                        Ok(_pos) => {}
-                        Err(pos) => {
-                            self.hyprland_state.workspaces.insert(
-                                pos,
-                                (
-                                    workspace_event_data.id,
-                                    workspace_event_data.name.to_string(),
-                                ),
-                            );
-                        }
-                    }
-                }
-                HyprlandEvent::ActiveWindowChanged(window_event_data) => {
-                    self.hyprland_state.activewindow = match window_event_data {
-                        Some(window_event) => window_event.title,
-                        None => "".to_string(),
-                    }
-                }
-                HyprlandEvent::ActiveMonitorChanged(monitor_event_data) => {
-                    if let Some(workspace_type) = monitor_event_data.workspace_name {
-                        let mut i = 0;
-                        let name = workspace_type.to_string();
-                        for workspace in self.hyprland_state.workspaces.clone() {
-                            if name == workspace.1 {
-                                self.hyprland_state.activeworkspaceindex = i;
-                                break;
-                            }
-                            i += 1;
-                        }
-                    }
-                }
-                _ => {}
-            },
-            Action::UpdatePipeWireState(pipewire_event) => match pipewire_event {
-                PipeWireEvent::UpdateVolumes(id, items) => {
-                    self.pipwire_state.update_volumes(id, items)
-                }
-                PipeWireEvent::UpdateMuted(id, muted) => self.pipwire_state.update_muted(id, muted),
-                PipeWireEvent::SetDefaultSinkName(name) => {
-                    self.pipwire_state.default_sink_name = name;
+    /// Records `event` when recording, then applies it.
+    fn handle(&mut self, event: Event) {
+        self.record(&event);
+        self.update(Self::action(event));
+    }
+
+    fn record(&mut self, event: &Event) {
+        let Some(recorder) = &mut self.recorder else {
+            return;
+        };
+        if let Err(error) = recorder.record(event) {
+            // Stop rather than leave a recording with holes in it
+            eprintln!("kbar: recording stopped: {}", error);
+            self.recorder = None;
+        }
+    }
+
+    /// Turns an event into the action that handles it.
+    fn action(event: Event) -> Action {
+        match event {
+            Event::Tick(timer) => Action::Tick(timer),
+            Event::Key(key) => Action::Key(key),
+            Event::Mouse(mouse) => Action::Mouse(mouse),
+            Event::Resize(_, _) => Action::Resize,
+            Event::UpdateHyprlandState(event) => Action::UpdateHyprlandState(event),
+            Event::HyprlandConnected(snapshot) => Action::HyprlandConnected(snapshot),
+            Event::HyprlandDisconnected => Action::HyprlandDisconnected,
+            Event::UpdatePipeWireState(event) => Action::UpdatePipeWireState(event),
+            Event::UpdateNetworkState(event) => Action::UpdateNetworkState(event),
+            Event::UpdateBatteryState(battery) => Action::UpdateBatteryState(battery),
+            Event::UpdateBluetoothBatteries(batteries) => Action::UpdateBluetoothBatteries(batteries),
+        }
+    }
+
+    fn update(&mut self, action: Action) {
+        let changed = match action {
+            Action::UpdateHyprlandState(hyprland_event) => {
+                self.hyprland_state.update(hyprland_event)
+            }
+            Action::HyprlandConnected(snapshot) => {
+                // Events may have been missed while disconnected, so start over from the snapshot.
+                // Without one the state is stale, but the events that follow still apply.
+                if let Some(snapshot) = snapshot {
+                    self.hyprland_state = HyprlandState {
+                        monitor: self.hyprland_state.monitor.take(),
+                        ..*snapshot
+                    };
                 }
-                PipeWireEvent::UpdateNodeId(id, name) => {
-                    self.pipwire_state.set_default_sink_id(name, id)
+                self.hyprland_state.connected = true;
+                true
+            }
+            Action::HyprlandDisconnected => {
+                std::mem::replace(&mut self.hyprland_state.connected, false)
+            }
+            Action::UpdatePipeWireState(pipewire_event) => self.pipwire_state.update(pipewire_event),
+            Action::UpdateNetworkState(network_event) => self.network_state.update(network_event),
+            Action::UpdateBatteryState(battery) => self.battery_state.update(battery),
+            Action::UpdateBluetoothBatteries(batteries) => {
+                self.pipwire_state.update_bluetooth_batteries(batteries)
+            }
+            // Input and resizes always redraw, the popups and the layout depend on them
+            Action::Key(key) => {
+                self.handle_key(key);
+                true
+            }
+            Action::Mouse(mouse) => {
+                self.handle_mouse(mouse);
+                true
+            }
+            Action::Resize => true,
+            Action::Tick(Timer::Marquee) => {
+                if self.title_scrolling {
+                    self.hyprland_state.tick();
                 }
-            },
+                self.title_scrolling
+            }
+            // The clock reads the time when drawn, the tick only redraws it when it shows another
+            Action::Tick(Timer::Clock) => {
+                let text = self.clock.text(self.now());
+                text != std::mem::replace(&mut self.clock_text, text.clone())
+            }
+            Action::None => false,
+        };
+        self.dirty |= changed;
+    }
 
-            Action::UpdateNetworkState(network_event) => match network_event {
-                NetEvent::Connect(_, ifindex) => {
-                    if let Some(ifindex) = ifindex {
-                        self.network_state.connected(ifindex).await;
-                    }
+    fn handle_key(&mut self, key: KeyEvent) {
+        // Raw mode swallows SIGINT, so quit on the key itself
+        if key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL) {
+            self.running = false;
+            return;
+        }
+        // An open popup takes the keyboard
+        let Some(overlay) = &mut self.overlay else {
+            let popup = match key.code {
+                KeyCode::Char('v') => Popup::Mixer,
+                KeyCode::Char('s') => Popup::Sinks,
+                KeyCode::Char('c') => Popup::Channels,
+                KeyCode::Char('d') => Popup::Calendar,
+                _ => return,
+            };
+            self.toggle_popup(popup);
+            return;
+        };
+        match (overlay.popup, key.code) {
+            (_, KeyCode::Esc | KeyCode::Char('q'))
+            | (Popup::Channels, KeyCode::Char('c'))
+            | (Popup::Calendar, KeyCode::Char('d')) => self.overlay = None,
+            (Popup::Mixer | Popup::Sinks, KeyCode::Up | KeyCode::Char('k')) => {
+                overlay.list.select_previous()
+            }
+            (Popup::Mixer | Popup::Sinks, KeyCode::Down | KeyCode::Char('j')) => {
+                overlay.list.select_next()
+            }
+            (Popup::Mixer, KeyCode::Left | KeyCode::Char('h')) => self.change_stream_volume(-5),
+            (Popup::Mixer, KeyCode::Right | KeyCode::Char('l')) => self.change_stream_volume(5),
+            (Popup::Mixer, KeyCode::Char('m')) => self.toggle_stream_muted(),
+            (Popup::Sinks, KeyCode::Enter) => self.select_sink(),
+            (Popup::Calendar, KeyCode::Left | KeyCode::Char('h')) => {
+                self.calendar = self.calendar - Months::new(1)
+            }
+            (Popup::Calendar, KeyCode::Right | KeyCode::Char('l')) => {
+                self.calendar = self.calendar + Months::new(1)
+            }
+            (Popup::Calendar, KeyCode::Char('t')) => self.calendar = self.now().date_naive(),
+            _ => {}
+        }
+    }
+
+    fn handle_mouse(&mut self, mouse: MouseEvent) {
+        let position = Position::new(mouse.column, mouse.row);
+        if self.overlay.as_ref().is_some_and(|overlay| overlay.contains(position)) {
+            self.handle_popup_mouse(mouse.kind, position);
+            return;
+        }
+        let MouseEventKind::Down(button) = mouse.kind else {
+            return;
+        };
+        if self.volume_area.contains(position) {
+            match button {
+                MouseButton::Right => self.toggle_popup(Popup::Sinks),
+                MouseButton::Middle => self.toggle_popup(Popup::Channels),
+                _ => self.toggle_popup(Popup::Mixer),
+            }
+        } else if self.clock_area.contains(position) {
+            self.toggle_popup(Popup::Calendar);
+        } else {
+            // Clicking anywhere but the popup dismisses it
+            self.overlay = None;
+        }
+    }
+
+    /// Clicks and scrolls on the open popup.
+    fn handle_popup_mouse(&mut self, kind: MouseEventKind, position: Position) {
+        let Some(overlay) = &mut self.overlay else {
+            return;
+        };
+        match (overlay.popup, kind) {
+            (Popup::Mixer | Popup::Sinks, MouseEventKind::ScrollUp) => overlay.list.select_previous(),
+            (Popup::Mixer | Popup::Sinks, MouseEventKind::ScrollDown) => overlay.list.select_next(),
+            (Popup::Mixer, MouseEventKind::Down(_)) => {
+                if let Some(row) = overlay.row(position, self.pipwire_state.streams.len()) {
+                    overlay.list.select(Some(row));
                 }
-                NetEvent::Disconnect => {
-                    self.network_state.disconnected();
+            }
+            (Popup::Sinks, MouseEventKind::Down(_)) => {
+                if let Some(row) = overlay.row(position, self.pipwire_state.sinks.len()) {
+                    overlay.list.select(Some(row));
+                    self.select_sink();
                 }
-                _ => (),
-            },
-            Action::Tick => {
-                self.battery_state.tick();
-                self.network_state.tick().await;
+            }
+            (Popup::Calendar, MouseEventKind::ScrollUp) => {
+                self.calendar = self.calendar - Months::new(1)
+            }
+            (Popup::Calendar, MouseEventKind::ScrollDown) => {
+                self.calendar = self.calendar + Months::new(1)
             }
             _ => {}
         }
     }
+
+    /// Opens `popup` under the widget it details, or closes it when it is open already.
+    fn toggle_popup(&mut self, popup: Popup) {
+        if self.overlay.as_ref().is_some_and(|overlay| overlay.popup == popup) {
+            self.overlay = None;
+            return;
+        }
+        if popup == Popup::Calendar {
+            self.calendar = self.now().date_naive();
+        }
+        self.overlay = Some(Overlay::new(popup, self.anchor(popup)));
+    }
+
+    /// Area of the widget `popup` details, where it hangs from.
+    pub fn anchor(&self, popup: Popup) -> Rect {
+        match popup {
+            Popup::Mixer | Popup::Sinks | Popup::Channels => self.volume_area,
+            Popup::Calendar => self.clock_area,
+        }
+    }
+
+    /// Lines the terminal should have, more than the bar's while a popup does not fit.
+    pub fn rows(&self) -> Option<u16> {
+        self.overlay.as_ref().map(|overlay| overlay.rows)
+    }
+
+    /// The time the clock shows.
+    pub fn now(&self) -> DateTime<FixedOffset> {
+        self.now.unwrap_or_else(|| Local::now().fixed_offset())
+    }
+
+    fn selected_stream(&self) -> Option<&crate::pipewirewidget::AudioStream> {
+        let streams = &self.pipwire_state.streams;
+        let selected = self.overlay.as_ref()?.list.selected()?;
+        let selected = selected.min(streams.len().checked_sub(1)?);
+        streams.get(selected)
+    }
+
+    fn change_stream_volume(&mut self, delta: i16) {
+        if let Some(stream) = self.selected_stream() {
+            let volumes = stream.volumes_changed_by(delta);
+            // Until PipeWire reports the stream's channels there is nothing to scale
+            if volumes.is_empty() {
+                return;
+            }
+            let _ = self
+                .pipewire_tx
+                .send(PipeWireCommand::SetVolumes(stream.id, volumes));
+        }
+    }
+
+    fn select_sink(&mut self) {
+        let sinks = &self.pipwire_state.sinks;
+        let Some(selected) = self.overlay.as_ref().and_then(|overlay| overlay.list.selected()) else {
+            return;
+        };
+        if let Some(sink) = sinks.get(selected.min(sinks.len().saturating_sub(1))) {
+            let _ = self
+                .pipewire_tx
+                .send(PipeWireCommand::SetDefaultSink(sink.name.clone()));
+        }
+    }
+
+    fn toggle_stream_muted(&mut self) {
+        if let Some(stream) = self.selected_stream() {
+            let _ = self
+                .pipewire_tx
+                .send(PipeWireCommand::SetMuted(stream.id, !stream.muted));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crossterm::event::KeyEventKind;
+    use hyprland::shared::Address;
+
+    use super::*;
+    use crate::batterywidget::BatteryChargingState;
+    use crate::hyprlandwidget::HyprlandWorkspace;
+    use crate::pipewirewidget::AudioSink;
+
+    fn click(app: &mut App, column: u16, row: u16) {
+        app.handle_mouse(MouseEvent {
+            kind: MouseEventKind::Down(MouseButton::Left),
+            column,
+            row,
+            modifiers: KeyModifiers::NONE,
+        });
+    }
+
+    fn open_mixer() -> App {
+        let mut app = App::offline();
+        for id in 50..54 {
+            app.pipwire_state.update_stream(id, "mpv".to_string(), "".to_string(), true);
+        }
+        app.volume_area = Rect::new(90, 0, 16, 1);
+        click(&mut app, 95, 0);
+        let overlay = app.overlay.as_mut().unwrap();
+        assert_eq!((overlay.popup, overlay.anchor), (Popup::Mixer, app.volume_area));
+        overlay.place(48, 6, Rect::new(0, 0, 120, 10));
+        app
+    }
+
+    #[test]
+    fn dismisses_popups_on_esc_and_outside_clicks() {
+        let mut app = open_mixer();
+        app.handle_key(KeyEvent::new_with_kind(
+            KeyCode::Esc,
+            KeyModifiers::NONE,
+            KeyEventKind::Press,
+        ));
+        assert!(app.overlay.is_none());
+
+        let mut app = open_mixer();
+        click(&mut app, 60, 3);
+        assert_eq!(app.overlay.as_ref().unwrap().list.selected(), Some(1));
+        click(&mut app, 10, 3);
+        assert!(app.overlay.is_none());
+    }
+
+    #[test]
+    fn switches_popups_on_the_same_widget() {
+        let mut app = open_mixer();
+        app.handle_mouse(MouseEvent {
+            kind: MouseEventKind::Down(MouseButton::Right),
+            column: 95,
+            row: 0,
+            modifiers: KeyModifiers::NONE,
+        });
+        assert_eq!(app.overlay.as_ref().map(|overlay| overlay.popup), Some(Popup::Sinks));
+        click(&mut app, 95, 0);
+        click(&mut app, 95, 0);
+        assert!(app.overlay.is_none());
+    }
+
+    /// A session as the monitors send it, starting from a Hyprland snapshot.
+    fn session() -> Vec<Event> {
+        let workspace = |id: i32| HyprlandWorkspace {
+            id,
+            name: id.to_string(),
+            monitor: "eDP-1".to_string(),
+        };
+        let snapshot = HyprlandState {
+            workspaces: vec![workspace(1), workspace(2)],
+            activeworkspace: 1,
+            keyboard_layout: "us".to_string(),
+            ..HyprlandState::default()
+        };
+        let address = Address::new("0x1");
+        vec![
+            Event::HyprlandConnected(Some(Box::new(snapshot))),
+            Event::UpdateHyprlandState(HyprlandEvent::WindowOpened {
+                address: address.clone(),
+                workspace: "2".to_string(),
+                class: "kitty".to_string(),
+            }),
+            Event::UpdateHyprlandState(HyprlandEvent::WorkspaceChanged { id: 2 }),
+            Event::UpdateHyprlandState(HyprlandEvent::ActiveWindowChanged {
+                address: Some(address),
+                class: "kitty".to_string(),
+                title: "~/src/kbar".to_string(),
+            }),
+            Event::UpdateNetworkState(NetworkEvent::Connected {
+                ssid: "home".to_string(),
+                signal_dbm: Some(-68),
+            }),
+            Event::UpdateBatteryState(BatteryState {
+                capacity: 9,
+                state: BatteryChargingState::Discharging,
+            }),
+            Event::UpdatePipeWireState(PipeWireEvent::Connected),
+            Event::UpdatePipeWireState(PipeWireEvent::UpdateSink(AudioSink {
+                id: 40,
+                name: "alsa_output.analog-stereo".to_string(),
+                description: "Built-in Audio".to_string(),
+                ..Default::default()
+            })),
+            Event::UpdatePipeWireState(PipeWireEvent::UpdateDefault(
+                "default.audio.sink".to_string(),
+                Some("alsa_output.analog-stereo".to_string()),
+            )),
+            Event::UpdatePipeWireState(PipeWireEvent::UpdateVolumes(40, vec![0.343, 0.343])),
+        ]
+    }
+
+    #[test]
+    fn connects_without_a_snapshot() {
+        let mut app = App::offline();
+        app.hyprland_state.workspaces = vec![HyprlandWorkspace {
+            id: 3,
+            name: "3".to_string(),
+            monitor: "eDP-1".to_string(),
+        }];
+        app.update(Action::HyprlandConnected(None));
+        assert!(app.hyprland_state.connected);
+        assert_eq!(app.hyprland_state.workspaces.len(), 1);
+    }
+
+    #[test]
+    fn redraws_only_on_changes() {
+        let mut app = App::offline();
+        app.now = Some(DateTime::parse_from_rfc3339("2025-03-14T09:26:05+01:00").unwrap());
+        app.update(Action::Tick(Timer::Clock));
+        app.dirty = false;
+
+        app.update(Action::Tick(Timer::Clock));
+        app.update(Action::Tick(Timer::Marquee));
+        app.update(Action::UpdateBatteryState(app.battery_state.clone()));
+        app.update(Action::UpdateNetworkState(NetworkEvent::Disconnected));
+        app.update(Action::UpdatePipeWireState(PipeWireEvent::RemoveNode(40)));
+        assert!(!app.dirty);
+
+        app.now = Some(DateTime::parse_from_rfc3339("2025-03-14T09:27:05+01:00").unwrap());
+        app.update(Action::Tick(Timer::Clock));
+        assert!(app.dirty);
+    }
+
+    #[test]
+    fn replays_a_recording_as_it_was_shown() {
+        let path = std::env::temp_dir().join(format!("kbar-replay-{}.jsonl", std::process::id()));
+        let path = path.to_str().unwrap();
+        let mut live = App::offline();
+        // Only the date, the live clock is a few milliseconds ahead of the recorded one
+        live.clock = toml::from_str("format = \"%Y-%m-%d\"").unwrap();
+        live.record_to(path).unwrap();
+        let mut terminal = Terminal::new(TestBackend::new(120, 1)).unwrap();
+        for event in session() {
+            live.handle(event);
+            terminal.draw(|frame| ui::render(&mut live, frame)).unwrap();
+        }
+
+        let mut replay = App::offline();
+        replay.clock = live.clock.clone();
+        let replayed = replay.replayed(path, 120).unwrap();
+        std::fs::remove_file(path).unwrap();
+        let buffer = replayed.backend().buffer();
+        assert_eq!(buffer, terminal.backend().buffer());
+        let bar: String = (0..120).map(|x| buffer[(x, 0)].symbol()).collect();
+        assert!(bar.contains("~/src/kbar") && bar.contains("home"), "{}", bar);
+    }
 }
@@ -1,96 +1,191 @@
-use std::fs::File;
-use std::io::prelude::Read;
+use std::time::Duration;
 
 use ratatui::{
     buffer::Buffer,
     layout::{Alignment, Rect},
+    style::Style,
+    text::Line,
     widgets::{Block, Padding, Paragraph, StatefulWidget, Widget},
 };
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone)]
-enum BatteryChargingState {
+use crate::event::{Event, EventSender};
+
+use crate::format::{Format, Formats, Values};
+use crate::icons::Icons;
+use crate::theme::Theme;
+
+/// How often the battery is re-read.
+pub const BATTERY_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Battery level at which `Theme::critical` applies.
+pub const CRITICAL_BATTERY: usize = 15;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum BatteryChargingState {
     Charging,
     Discharging,
 }
 
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct BatteryState {
-    ticks: u8,
     pub capacity: usize,
     pub state: BatteryChargingState,
 }
 
 impl BatteryState {
     pub fn new() -> Self {
-        let mut new_battery_state = Self {ticks:0, capacity:0, state:BatteryChargingState::Discharging };
-        new_battery_state.update();
-        new_battery_state
+        Self {capacity:0, state:BatteryChargingState::Discharging }
     }
 
-    fn update(&mut self) {
-        let mut charger = File::open("/sys/class/power_supply/ADP1/online").unwrap();
-        let mut battery = File::open("/sys/class/power_supply/BAT0/capacity").unwrap();
+    /// Takes a new reading, returning whether it differs from the last one.
+    pub fn update(&mut self, battery: BatteryState) -> bool {
+        let changed = *self != battery;
+        *self = battery;
+        changed
+    }
 
-        let mut buffer = [0; 3];
+    /// Reads the power supply, `None` on machines without a battery.
+    pub fn read() -> Option<Self> {
+        let online = std::fs::read_to_string("/sys/class/power_supply/ADP1/online").ok()?;
+        let capacity = std::fs::read_to_string("/sys/class/power_supply/BAT0/capacity").ok()?;
+        Self::parse(&online, &capacity)
+    }
 
-        let _ = charger.read(&mut buffer);
-        self.state = if buffer[0] == ('1' as u8) {
-            BatteryChargingState::Charging
-        } else {
-            BatteryChargingState::Discharging
+    /// Parses the contents of the charger's `online` and the battery's `capacity` files.
+    fn parse(online: &str, capacity: &str) -> Option<Self> {
+        let state = match online.trim() {
+            "1" => BatteryChargingState::Charging,
+            _ => BatteryChargingState::Discharging,
         };
-        let _ = battery.read(&mut buffer);
-        self.capacity = if buffer[2] == ('0' as u8) {
-            100
-        } else if buffer[1] == 0 {
-            buffer[0] - ('0' as u8)
-        } else {
-            (buffer[0] - ('0' as u8)) * 10 + (buffer[1] - '0' as u8)
-        } as usize;
+        let capacity = capacity.trim().parse().ok()?;
+        Some(Self { capacity, state })
     }
+}
 
-    pub fn tick(&mut self) {
-        self.ticks+=1 ;
-
-        if self.ticks >= 100 {
-            self.update();
-            self.ticks = 0;
+/// Sends the battery to `sender` every `BATTERY_INTERVAL`, machines without one send nothing.
+pub fn battery_monitor(sender: EventSender) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(BATTERY_INTERVAL);
+        loop {
+            interval.tick().await;
+            let Some(battery) = BatteryState::read() else {
+                continue;
+            };
+            if sender.send(Event::UpdateBatteryState(battery)).await.is_err() {
+                return;
+            }
         }
-    }
+    });
 }
 
-pub struct BatteryWidget {
+/// Shows the battery level, and whether it is charging.
+///
+/// Format variables: `icon`, `capacity` in percent and `status`, "charging" or "discharging".
+pub struct BatteryWidget<'a> {
     alignment: Alignment,
+    format: &'a Format,
+    icons: &'a Icons,
+    charging_style: Style,
+    critical_style: Style,
 }
 
-impl<'a> BatteryWidget {
+impl<'a> BatteryWidget<'a> {
+    pub const FORMAT: &'static str = "{icon} {capacity}%";
+    pub const VARIABLES: &'static [&'static str] = &["icon", "capacity", "status"];
+
     pub fn new() -> Self {
+        let theme = Theme::default();
         Self {
             alignment: Alignment::Left,
+            format: &Formats::shared().battery,
+            icons: Icons::shared(),
+            charging_style: theme.charging,
+            critical_style: theme.critical,
         }
     }
+
+    pub fn theme(&mut self, theme: &Theme) {
+        self.charging_style = theme.charging;
+        self.critical_style = theme.critical;
+    }
+
+    pub fn format(&mut self, format: &'a Format) {
+        self.format = format;
+    }
+
+    pub fn icons(&mut self, icons: &'a Icons) {
+        self.icons = icons;
+    }
+
     pub fn right_aligned(&mut self) {
         self.alignment = Alignment::Right;
     }
     pub fn center_aligned(&mut self) {
         self.alignment = Alignment::Center;
     }
+
+    /// Width needed to show the battery.
+    pub fn width(&self, state: &BatteryState) -> u16 {
+        self.line(state).width() as u16
+    }
+
+    fn line(&self, state: &BatteryState) -> Line<'static> {
+        let levels = match state.state {
+            BatteryChargingState::Charging => &self.icons.battery_charging,
+            BatteryChargingState::Discharging => &self.icons.battery_discharging,
+        };
+        let icon = Icons::level(levels, state.capacity, 10);
+        let status = match state.state {
+            BatteryChargingState::Charging => "charging",
+            BatteryChargingState::Discharging => "discharging",
+        };
+        let values = Values::new()
+            .set("icon", icon)
+            .set("capacity", state.capacity.to_string())
+            .set("status", status);
+        self.format.line(&values)
+    }
 }
 
-impl StatefulWidget for BatteryWidget {
+impl StatefulWidget for BatteryWidget<'_> {
     type State = BatteryState;
     fn render(self, area: Rect, buf: &mut Buffer, state: &mut BatteryState) {
-        let icon = match state.state {
-            BatteryChargingState::Charging => {
-                ["󰢟", "󰢜", "󰂆", "󰂇", "󰂈", "󰢝", "󰂉", "󰢞", "󰂊", "󰂋", "󰁹"][(state.capacity) / 10]
-            }
-            BatteryChargingState::Discharging => {
-                ["󰂎", "󰁺", "󰁻", "󰁼", "󰁽", "󰁾", "󰁿", "󰂀", "󰂁", "󰂂", "󰁹"][(state.capacity) / 10]
+        let style = match state.state {
+            BatteryChargingState::Charging => self.charging_style,
+            BatteryChargingState::Discharging if state.capacity <= CRITICAL_BATTERY => {
+                self.critical_style
             }
+            BatteryChargingState::Discharging => Style::new(),
         };
-        Paragraph::new(format!("{} {}%", icon, state.capacity))
+        Paragraph::new(self.line(state))
+            .style(style)
             .alignment(self.alignment)
             .render(area, buf)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_the_power_supply() {
+        assert_eq!(
+            BatteryState::parse("0\n", "5\n"),
+            Some(BatteryState {
+                capacity: 5,
+                state: BatteryChargingState::Discharging,
+            })
+        );
+        assert_eq!(
+            BatteryState::parse("1\n", "100\n"),
+            Some(BatteryState {
+                capacity: 100,
+                state: BatteryChargingState::Charging,
+            })
+        );
+        assert_eq!(BatteryState::parse("0\n", ""), None);
+    }
+}
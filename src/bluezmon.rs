@@ -0,0 +1,106 @@
+//! Battery levels of Bluetooth devices, read from BlueZ over D-Bus.
+//!
+//! PipeWire does not carry them, BlueZ publishes what a headset reports on the device's object
+//! with the `org.bluez.Battery1` interface.
+
+use std::collections::HashMap;
+
+use zbus::{Connection, fdo::ObjectManagerProxy, zvariant::OwnedValue};
+
+use crate::batterywidget::BATTERY_INTERVAL;
+use crate::event::{Event, EventSender};
+
+const DEVICE_INTERFACE: &str = "org.bluez.Device1";
+const BATTERY_INTERFACE: &str = "org.bluez.Battery1";
+
+/// Sends the battery percentage of every Bluetooth device reporting one to `sender`, by address,
+/// every `BATTERY_INTERVAL`. Machines without a system bus send nothing.
+pub fn bluez_monitor(sender: EventSender) {
+    tokio::spawn(async move {
+        let Ok(connection) = Connection::system().await else {
+            return;
+        };
+        let Ok(manager) = object_manager(&connection).await else {
+            return;
+        };
+        let mut interval = tokio::time::interval(BATTERY_INTERVAL);
+        loop {
+            interval.tick().await;
+            // BlueZ may not be running yet or restarting, the next reading tries again
+            let Ok(objects) = manager.get_managed_objects().await else {
+                continue;
+            };
+            let objects = objects.into_values().map(|interfaces| {
+                interfaces
+                    .into_iter()
+                    .map(|(name, properties)| (name.to_string(), properties))
+                    .collect()
+            });
+            if sender
+                .send(Event::UpdateBluetoothBatteries(batteries(objects)))
+                .await
+                .is_err()
+            {
+                return;
+            }
+        }
+    });
+}
+
+async fn object_manager(connection: &Connection) -> zbus::Result<ObjectManagerProxy<'_>> {
+    ObjectManagerProxy::builder(connection)
+        .destination("org.bluez")?
+        .path("/")?
+        .build()
+        .await
+}
+
+/// Battery percentages by device address, from the interfaces of each of BlueZ's objects.
+fn batteries(
+    objects: impl Iterator<Item = HashMap<String, HashMap<String, OwnedValue>>>,
+) -> HashMap<String, u8> {
+    objects
+        .filter_map(|interfaces| {
+            let address = interfaces.get(DEVICE_INTERFACE)?.get("Address")?;
+            let percentage = interfaces.get(BATTERY_INTERFACE)?.get("Percentage")?;
+            Some((
+                address.downcast_ref::<&str>().ok()?.to_string(),
+                percentage.downcast_ref::<u8>().ok()?,
+            ))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zbus::zvariant::Value;
+
+    fn object(interfaces: &[(&str, &str, Value)]) -> HashMap<String, HashMap<String, OwnedValue>> {
+        let mut object: HashMap<String, HashMap<String, OwnedValue>> = HashMap::new();
+        for (interface, property, value) in interfaces {
+            object.entry(interface.to_string()).or_default().insert(
+                property.to_string(),
+                value.try_to_owned().unwrap(),
+            );
+        }
+        object
+    }
+
+    #[test]
+    fn reads_batteries_by_address() {
+        let objects = vec![
+            object(&[("org.bluez.Adapter1", "Address", Value::from("00:1A:7D:DA:71:13"))]),
+            object(&[
+                (DEVICE_INTERFACE, "Address", Value::from("AC:80:0A:2B:17:5E")),
+                (BATTERY_INTERFACE, "Percentage", Value::from(80u8)),
+            ]),
+            // A keyboard without a battery
+            object(&[(DEVICE_INTERFACE, "Address", Value::from("F4:73:35:1C:02:9A"))]),
+        ];
+        assert_eq!(
+            batteries(objects.into_iter()),
+            HashMap::from([("AC:80:0A:2B:17:5E".to_string(), 80)])
+        );
+    }
+}
@@ -0,0 +1,269 @@
+use std::time::Duration;
+
+use chrono::format::{Fixed, Item, Numeric, StrftimeItems};
+use chrono::{DateTime, Datelike, FixedOffset, Months, NaiveDate, TimeZone as _};
+use chrono_tz::Tz;
+use ratatui::{
+    buffer::Buffer,
+    layout::Rect,
+    style::{Style, Stylize},
+    text::{Line, Span},
+    widgets::{Block, Clear, Paragraph, Widget},
+};
+use serde::Deserialize;
+use unicode_width::UnicodeWidthStr;
+
+use crate::theme::Theme;
+
+/// What the clock shows, from the config's `[clock]` section.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "ClockConfig")]
+pub struct Clock {
+    /// A `strftime` format for the local time.
+    pub format: String,
+    /// Other places whose time is shown after the local one.
+    pub timezones: Vec<ZoneConfig>,
+    /// Whether any of the formats shows seconds, so the clock needs a tick every second.
+    seconds: bool,
+}
+
+impl Default for Clock {
+    fn default() -> Self {
+        Self::from_config(&ClockConfig::default()).expect("default clock format parses")
+    }
+}
+
+impl Clock {
+    /// Builds the clock a config's `[clock]` section describes.
+    pub fn from_config(config: &ClockConfig) -> Result<Self, String> {
+        let mut seconds = shows_seconds(&config.format)?;
+        for zone in &config.timezones {
+            seconds |= shows_seconds(&zone.format)?;
+        }
+        Ok(Self {
+            format: config.format.clone(),
+            timezones: config.timezones.clone(),
+            seconds,
+        })
+    }
+
+    /// How often the clock has to be redrawn.
+    pub fn tick(&self) -> Duration {
+        match self.seconds {
+            true => Duration::from_secs(1),
+            false => Duration::from_secs(60),
+        }
+    }
+
+    /// The local time, followed by the time in each of the other timezones.
+    pub fn text(&self, now: DateTime<FixedOffset>) -> String {
+        let mut text = now.format(&self.format).to_string();
+        for zone in &self.timezones {
+            let time = zone.timezone.from_utc_datetime(&now.naive_utc());
+            text.push_str(&format!("  {} {}", zone.label, time.format(&zone.format)));
+        }
+        text
+    }
+}
+
+impl TryFrom<ClockConfig> for Clock {
+    type Error = String;
+
+    fn try_from(config: ClockConfig) -> Result<Self, String> {
+        Self::from_config(&config)
+    }
+}
+
+/// Whether `format` shows seconds, failing if it is not a valid `strftime` format.
+fn shows_seconds(format: &str) -> Result<bool, String> {
+    let items = StrftimeItems::new(format)
+        .parse()
+        .map_err(|_| format!("bad clock format \"{}\"", format))?;
+    Ok(items.iter().any(|item| {
+        matches!(
+            item,
+            Item::Numeric(Numeric::Second | Numeric::Timestamp, _)
+                | Item::Fixed(Fixed::RFC2822 | Fixed::RFC3339)
+        )
+    }))
+}
+
+/// The `[clock]` section of the config file.
+///
+/// ```toml
+/// [clock]
+/// format = "%a %b %d %H:%M:%S"
+/// timezones = [
+///     { label = "UTC", timezone = "UTC" },
+///     { label = "SF", timezone = "America/Los_Angeles", format = "%H:%M %a" },
+/// ]
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClockConfig {
+    pub format: String,
+    pub timezones: Vec<ZoneConfig>,
+}
+
+impl Default for ClockConfig {
+    fn default() -> Self {
+        Self {
+            format: "%a %b %d %H:%M".to_string(),
+            timezones: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ZoneConfig {
+    /// Shown in front of the time, e.g. a city.
+    pub label: String,
+    /// An IANA timezone, e.g. "Europe/Berlin".
+    pub timezone: Tz,
+    #[serde(default = "ZoneConfig::default_format")]
+    pub format: String,
+}
+
+impl ZoneConfig {
+    fn default_format() -> String {
+        "%H:%M".to_string()
+    }
+}
+
+/// Shows the time, in the local timezone and the configured ones.
+pub struct ClockWidget<'a> {
+    clock: &'a Clock,
+    now: DateTime<FixedOffset>,
+}
+
+impl<'a> ClockWidget<'a> {
+    pub fn new(clock: &'a Clock, now: DateTime<FixedOffset>) -> Self {
+        Self { clock, now }
+    }
+
+    /// Width needed to show every timezone.
+    pub fn width(&self) -> u16 {
+        self.clock.text(self.now).width() as u16
+    }
+}
+
+impl Widget for ClockWidget<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        Paragraph::new(self.clock.text(self.now)).render(area, buf);
+    }
+}
+
+/// Popup showing a month, with ISO week numbers and today highlighted.
+pub struct CalendarWidget {
+    /// Any day of the month shown.
+    month: NaiveDate,
+    today: NaiveDate,
+    highlight_style: Style,
+    week_style: Style,
+}
+
+impl CalendarWidget {
+    /// Width of the week number column and the seven days, with the border.
+    pub const WIDTH: u16 = 3 + 7 * 3 + 2;
+
+    pub fn new(month: NaiveDate, today: NaiveDate) -> Self {
+        let theme = Theme::default();
+        Self {
+            month,
+            today,
+            highlight_style: theme.highlight,
+            week_style: theme.separator,
+        }
+    }
+
+    pub fn theme(&mut self, theme: &Theme) {
+        self.highlight_style = theme.highlight;
+        self.week_style = theme.separator;
+    }
+
+    /// The header, a row per week and the border.
+    pub fn height(month: NaiveDate) -> u16 {
+        CalendarWidget::weeks(month).len() as u16 + 3
+    }
+
+    /// The Monday starting each week that has days of `month`.
+    fn weeks(month: NaiveDate) -> Vec<NaiveDate> {
+        let first = month.with_day(1).unwrap_or(month);
+        let next = first + Months::new(1);
+        let mut monday = first.week(chrono::Weekday::Mon).first_day();
+        let mut weeks = Vec::new();
+        while monday < next {
+            weeks.push(monday);
+            monday = monday + chrono::Days::new(7);
+        }
+        weeks
+    }
+}
+
+impl Widget for CalendarWidget {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let title = format!(" {} ", self.month.format("%B %Y"));
+        let block = Block::bordered().title(title);
+        let inner = block.inner(area);
+        Clear.render(area, buf);
+        block.render(area, buf);
+        let mut lines = vec![Line::raw(" Wk Mo Tu We Th Fr Sa Su").bold()];
+        for monday in CalendarWidget::weeks(self.month) {
+            let week = format!(" {:>2}", monday.iso_week().week());
+            let mut spans = vec![Span::styled(week, self.week_style)];
+            for day in monday.iter_days().take(7) {
+                spans.push(Span::raw(" "));
+                let text = format!("{:>2}", day.day());
+                spans.push(match day {
+                    day if day.month() != self.month.month() => Span::raw("  "),
+                    day if day == self.today => Span::styled(text, self.highlight_style),
+                    _ => Span::raw(text),
+                });
+            }
+            lines.push(Line::from(spans));
+        }
+        Paragraph::new(lines).render(inner, buf);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clock(config: &str) -> Result<Clock, toml::de::Error> {
+        toml::from_str(config)
+    }
+
+    #[test]
+    fn shows_other_timezones() {
+        let clock = clock(
+            r#"
+            format = "%H:%M:%S"
+            timezones = [
+                { label = "UTC", timezone = "UTC" },
+                { label = "SF", timezone = "America/Los_Angeles", format = "%H:%M %a" },
+            ]
+            "#,
+        )
+        .unwrap();
+        let now = DateTime::parse_from_rfc3339("2025-03-14T09:26:05+01:00").unwrap();
+        assert_eq!(clock.text(now), "09:26:05  UTC 08:26  SF 01:26 Fri");
+        assert_eq!(clock.tick(), Duration::from_secs(1));
+        assert_eq!(Clock::default().tick(), Duration::from_secs(60));
+        assert!(self::clock("format = \"%Q\"").is_err());
+        assert!(self::clock("timezones = [{ label = \"X\", timezone = \"Mars/Olympus\" }]").is_err());
+    }
+
+    #[test]
+    fn numbers_iso_weeks() {
+        // March 2025 starts on a Saturday, in ISO week 9, and ends on a Monday in week 14
+        let weeks = CalendarWidget::weeks(NaiveDate::from_ymd_opt(2025, 3, 14).unwrap());
+        let numbers: Vec<u32> = weeks.iter().map(|monday| monday.iso_week().week()).collect();
+        assert_eq!(numbers, [9, 10, 11, 12, 13, 14]);
+        assert_eq!(weeks[0], NaiveDate::from_ymd_opt(2025, 2, 24).unwrap());
+        // January 1st 2021 is a Friday, still in the last ISO week of 2020
+        let weeks = CalendarWidget::weeks(NaiveDate::from_ymd_opt(2021, 1, 1).unwrap());
+        assert_eq!(weeks[0].iso_week().week(), 53);
+    }
+}
@@ -0,0 +1,51 @@
+use std::path::PathBuf;
+
+use serde::Deserialize;
+
+use crate::app::AppResult;
+use crate::clockwidget::Clock;
+use crate::format::Formats;
+use crate::icons::Icons;
+use crate::theme::Theme;
+
+/// The config file, every section is optional.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Only show workspaces on this monitor, `$KBAR_MONITOR` overrides it.
+    pub monitor: Option<String>,
+    pub theme: Theme,
+    pub clock: Clock,
+    pub icons: Icons,
+    pub format: Formats,
+}
+
+impl Config {
+    /// Reads `$KBAR_CONFIG`, or `kbar/config.toml` in `$XDG_CONFIG_HOME` (`~/.config`).
+    ///
+    /// A missing file gives the defaults, a broken one is an error naming the file.
+    pub fn load() -> AppResult<Self> {
+        let Some(path) = Self::path() else {
+            return Ok(Self::default());
+        };
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(Self::default());
+            }
+            Err(error) => return Err(format!("{}: {}", path.display(), error).into()),
+        };
+        toml::from_str(&text).map_err(|error| format!("{}: {}", path.display(), error).into())
+    }
+
+    fn path() -> Option<PathBuf> {
+        if let Some(path) = std::env::var_os("KBAR_CONFIG") {
+            return Some(path.into());
+        }
+        let config = match std::env::var_os("XDG_CONFIG_HOME") {
+            Some(config) => PathBuf::from(config),
+            None => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+        };
+        Some(config.join("kbar").join("config.toml"))
+    }
+}
@@ -0,0 +1,224 @@
+//! Synthetic sources for `kbar --demo`, so the bar can be worked on in any terminal, without
+//! Hyprland, PipeWire or a wifi card.
+
+use std::time::Duration;
+
+use hyprland::shared::Address;
+
+use crate::batterywidget::{BATTERY_INTERVAL, BatteryChargingState, BatteryState};
+use crate::event::{Event, EventSender};
+use crate::hyprmon::HyprlandEvent;
+use crate::networkwidget::{Connection, NETWORK_INTERVAL, NetworkEvent, NetworkState};
+use crate::pipemon::PipeWireEvent;
+use crate::pipewirewidget::{AudioSink, SinkKind};
+
+/// How often the scripted session moves on.
+const STEP: Duration = Duration::from_secs(2);
+
+/// Class and title of the window opened on each workspace, the last workspace stays empty.
+const WINDOWS: [(&str, &str); 4] = [
+    ("kitty", "~/src/kbar"),
+    ("firefox", "ratatui - Rust TUI library - Mozilla Firefox"),
+    ("Slack", "#kbar | Slack"),
+    ("mpv", "Big Buck Bunny.mkv - mpv"),
+];
+const WORKSPACES: i32 = WINDOWS.len() as i32 + 1;
+
+/// Networks the wifi roams between as the signal fades.
+const NETWORKS: [&str; 3] = ["home", "home-5G", "office"];
+
+const SINK_ID: u32 = 40;
+const SINK_NAME: &str = "alsa_output.pci-0000_00_1f.3.analog-stereo";
+
+/// Sends a scripted session to `sender`: workspace switches, volume changes and mutes, with the
+/// battery draining and the wifi roaming at the pace the real ones are read.
+pub fn demo_monitor(sender: EventSender) {
+    let script = sender.clone();
+    tokio::spawn(async move {
+        let mut step = tokio::time::interval(STEP);
+        for n in 0.. {
+            step.tick().await;
+            let events = match n {
+                0 => setup(),
+                n => next(n),
+            };
+            for event in events {
+                if script.send(event).await.is_err() {
+                    return;
+                }
+            }
+        }
+    });
+    tokio::spawn(async move {
+        let mut battery = BatteryState {
+            capacity: 76,
+            state: BatteryChargingState::Discharging,
+        };
+        let mut network = NetworkState::new();
+        let mut battery_interval = tokio::time::interval(BATTERY_INTERVAL);
+        let mut network_interval = tokio::time::interval(NETWORK_INTERVAL);
+        loop {
+            let event = tokio::select! {
+                _ = battery_interval.tick() => {
+                    battery = drain(&battery);
+                    Event::UpdateBatteryState(battery.clone())
+                }
+                _ = network_interval.tick() => {
+                    let event = roam(&network);
+                    network.update(event.clone());
+                    Event::UpdateNetworkState(event)
+                }
+            };
+            if sender.send(event).await.is_err() {
+                return;
+            }
+        }
+    });
+}
+
+/// Workspaces, their windows and a default sink, as a session would start with.
+fn setup() -> Vec<Event> {
+    let mut events = vec![Event::HyprlandConnected(Some(Box::default()))];
+    for id in 1..=WORKSPACES {
+        events.push(hyprland(HyprlandEvent::WorkspaceAdded {
+            id,
+            name: id.to_string(),
+        }));
+    }
+    for (id, (class, _)) in (1..).zip(WINDOWS) {
+        events.push(hyprland(HyprlandEvent::WindowOpened {
+            address: window(id),
+            workspace: id.to_string(),
+            class: class.to_string(),
+        }));
+    }
+    events.extend(focus(1));
+    events.push(hyprland(HyprlandEvent::LayoutChanged("English (US)".to_string())));
+    events.extend([
+        pipewire(PipeWireEvent::Connected),
+        pipewire(PipeWireEvent::UpdateSink(AudioSink {
+            id: SINK_ID,
+            name: SINK_NAME.to_string(),
+            description: "Built-in Audio Analog Stereo".to_string(),
+            kind: SinkKind::Speakers,
+            ..Default::default()
+        })),
+        pipewire(PipeWireEvent::UpdateDefault(
+            "default.audio.sink".to_string(),
+            Some(SINK_NAME.to_string()),
+        )),
+        pipewire(PipeWireEvent::UpdateVolumes(SINK_ID, vec![0.3, 0.3])),
+    ]);
+    events
+}
+
+/// What happens at step `n`, cycling through a workspace switch, a volume change and a mute toggle.
+fn next(n: u32) -> Vec<Event> {
+    match n % 3 {
+        0 => focus((n / 3) as i32 % WORKSPACES + 1),
+        1 => {
+            // Sweeps up and down between 20% and 100%, in the cubic scale PipeWire uses
+            let level = (n / 3 % 16) as f32;
+            let volume = 0.2 + 0.8 * (level - 8.0).abs() / 8.0;
+            vec![pipewire(PipeWireEvent::UpdateVolumes(
+                SINK_ID,
+                vec![volume.powi(3); 2],
+            ))]
+        }
+        _ => vec![pipewire(PipeWireEvent::UpdateMuted(SINK_ID, n.is_multiple_of(4)))],
+    }
+}
+
+fn focus(id: i32) -> Vec<Event> {
+    let activewindow = match WINDOWS.get(id as usize - 1) {
+        Some((class, title)) => HyprlandEvent::ActiveWindowChanged {
+            address: Some(window(id)),
+            class: class.to_string(),
+            title: title.to_string(),
+        },
+        None => HyprlandEvent::ActiveWindowChanged {
+            address: None,
+            class: "".to_string(),
+            title: "".to_string(),
+        },
+    };
+    vec![
+        hyprland(HyprlandEvent::WorkspaceChanged { id }),
+        hyprland(activewindow),
+    ]
+}
+
+fn window(id: i32) -> Address {
+    Address::new(format!("{:x}", 0x5500 + id))
+}
+
+fn hyprland(event: HyprlandEvent) -> Event {
+    Event::UpdateHyprlandState(event)
+}
+
+fn pipewire(event: PipeWireEvent) -> Event {
+    Event::UpdatePipeWireState(event)
+}
+
+/// The battery a few percent drained, or charged back up once it runs low.
+fn drain(battery: &BatteryState) -> BatteryState {
+    let mut battery = battery.clone();
+    match battery.state {
+        BatteryChargingState::Discharging if battery.capacity <= 10 => {
+            battery.state = BatteryChargingState::Charging;
+        }
+        BatteryChargingState::Discharging => battery.capacity -= 3,
+        BatteryChargingState::Charging if battery.capacity >= 100 => {
+            battery.state = BatteryChargingState::Discharging;
+        }
+        BatteryChargingState::Charging => battery.capacity = (battery.capacity + 10).min(100),
+    }
+    battery
+}
+
+/// The wifi signal faded a bit, or a roam to the next network once it got weak.
+fn roam(network: &NetworkState) -> NetworkEvent {
+    match (&network.state, network.signal_dbm) {
+        (Connection::Connected, Some(signal_dbm)) if signal_dbm > -85 => {
+            return NetworkEvent::Signal(signal_dbm - 4);
+        }
+        _ => {}
+    }
+    let next = NETWORKS
+        .iter()
+        .position(|ssid| *ssid == network.ssid)
+        .map_or(0, |current| current + 1);
+    NetworkEvent::Connected {
+        ssid: NETWORKS[next % NETWORKS.len()].to_string(),
+        signal_dbm: Some(-52),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roams_to_the_next_network() {
+        let mut network = NetworkState::new();
+        let mut ssids = Vec::new();
+        for _ in 0..40 {
+            network.update(roam(&network));
+            if !ssids.contains(&network.ssid) {
+                ssids.push(network.ssid.clone());
+            }
+        }
+        assert_eq!(ssids, NETWORKS);
+    }
+
+    #[test]
+    fn charges_once_drained() {
+        let mut battery = BatteryState::new();
+        battery.capacity = 40;
+        for _ in 0..20 {
+            battery = drain(&battery);
+            assert!(battery.capacity <= 100);
+        }
+        assert!(matches!(battery.state, BatteryChargingState::Charging));
+    }
+}
@@ -1,80 +1,315 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::time::Duration;
 
-use crossterm::event::MouseEvent;
+use crossterm::event::{Event as CrosstermEvent, KeyEvent, KeyEventKind, MouseEvent};
 use futures::{FutureExt, StreamExt};
-use neli::genl::Genlmsghdr;
-use neli::router::asynchronous::{NlRouter, NlRouterReceiverHandle};
 use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::TrySendError;
 
-use hyprland::event_listener::Event as HyprlandEvent;
+use serde::{Deserialize, Serialize};
 
 use crate::app::AppResult;
-use crate::network;
-use crate::pipemon::{PipeWireEvent, pw_monitor};
-use crate::network::nl80211_stream::Event as NetEvent;
+use crate::batterywidget::{BatteryState, battery_monitor};
+use crate::bluezmon::bluez_monitor;
+use crate::demo::demo_monitor;
+use crate::hyprlandwidget::HyprlandState;
+use crate::hyprmon::{HyprlandEvent, hypr_monitor};
+use crate::networkwidget::{NetworkEvent, network_monitor};
+use crate::pipemon::{PipeWireCommand, PipeWireEvent, pw_monitor};
 
 use std::sync::{Arc, Mutex};
 
 
+/// How many events can wait for the app before producers are held back.
+const EVENT_CAPACITY: usize = 256;
+
+/// Which module a timer tick is for, each runs at its own pace.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Timer {
+    /// Fires on minute boundaries, or every second when the clock shows seconds.
+    Clock,
+    /// Scrolls a window title that does not fit, driven by the app only while one is scrolling.
+    Marquee,
+}
+
 /// Terminal events.
 
 // #[derive(Clone, Copy, Debug)]
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum Event {
-    /// Terminal tick.
-    Render,
-    Tick,
+    /// A module's timer fired.
+    Tick(Timer),
     /// Key press.
-    // Key(KeyEvent),
+    Key(KeyEvent),
     /// Mouse click/scroll.
     UpdateHyprlandState(HyprlandEvent),
+    /// Hyprland is reachable again, with a fresh snapshot of its state unless taking it failed.
+    HyprlandConnected(Option<Box<HyprlandState>>),
+    /// The Hyprland event socket was lost.
+    HyprlandDisconnected,
     // HyprlandWorkspaceEvent(HyprlandEvent),
     // HyprlandWindowEvent(HyprlandEvent),
     UpdatePipeWireState(PipeWireEvent),
-    UpdateNetworkState(NetEvent),
+    UpdateNetworkState(NetworkEvent),
+    /// A fresh reading of the battery.
+    UpdateBatteryState(BatteryState),
+    /// Battery percentages of Bluetooth devices, by address.
+    UpdateBluetoothBatteries(HashMap<String, u8>),
     Mouse(MouseEvent),
     /// Terminal resize.
     Resize(u16, u16),
 }
 
+/// Updates where only the newest one matters, keyed by what they update.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum Coalesce {
+    Volumes(u32),
+    Muted(u32),
+    ChannelMap(u32),
+    Stream(u32),
+    Capture(u32),
+}
+
+impl Coalesce {
+    fn of(event: &Event) -> Option<Self> {
+        let Event::UpdatePipeWireState(event) = event else {
+            return None;
+        };
+        match event {
+            PipeWireEvent::UpdateVolumes(id, _) => Some(Coalesce::Volumes(*id)),
+            PipeWireEvent::UpdateMuted(id, _) => Some(Coalesce::Muted(*id)),
+            PipeWireEvent::UpdateChannelMap(id, _) => Some(Coalesce::ChannelMap(*id)),
+            PipeWireEvent::UpdateStream(id, ..) => Some(Coalesce::Stream(*id)),
+            PipeWireEvent::UpdateCapture(capture) => Some(Coalesce::Capture(capture.id)),
+            _ => None,
+        }
+    }
+
+    /// The node the update is for.
+    fn id(&self) -> u32 {
+        match self {
+            Coalesce::Volumes(id)
+            | Coalesce::Muted(id)
+            | Coalesce::ChannelMap(id)
+            | Coalesce::Stream(id)
+            | Coalesce::Capture(id) => *id,
+        }
+    }
+}
+
+/// What travels through the queue, coalesced events wait on the side until they are received.
+#[derive(Debug)]
+enum Queued {
+    Event(Event),
+    Latest(Coalesce),
+}
+
+/// Counts events that did not reach the app the way they were sent.
+#[derive(Debug, Default)]
+pub struct EventMetrics {
+    coalesced: AtomicU64,
+    dropped: AtomicU64,
+}
+
+impl EventMetrics {
+    /// Updates replaced by a newer one for the same thing before the app got to them.
+    pub fn coalesced(&self) -> u64 {
+        self.coalesced.load(Ordering::Relaxed)
+    }
+
+    /// Ticks skipped because the queue was full, and events sent after the app stopped.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+/// The app is no longer receiving events.
+#[derive(Debug)]
+pub struct Closed;
+
+/// Sends events to the app through a bounded queue, shared by every monitor.
+#[derive(Clone, Debug)]
+pub struct EventSender {
+    sender: mpsc::Sender<Queued>,
+    latest: Arc<Mutex<HashMap<Coalesce, Event>>>,
+    metrics: Arc<EventMetrics>,
+}
+
+impl EventSender {
+    fn channel(capacity: usize) -> (Self, mpsc::Receiver<Queued>) {
+        let (sender, receiver) = mpsc::channel(capacity);
+        let sender = Self {
+            sender,
+            latest: Arc::new(Mutex::new(HashMap::new())),
+            metrics: Arc::new(EventMetrics::default()),
+        };
+        (sender, receiver)
+    }
+
+    /// Queues `event`, waiting for room when the app is behind.
+    pub async fn send(&self, event: Event) -> Result<(), Closed> {
+        match self.coalesce(event) {
+            Some(queued) => self.sender.send(queued).await.map_err(|_| self.closed_error()),
+            None => Ok(()),
+        }
+    }
+
+    /// Like [`EventSender::send`], for threads outside the tokio runtime.
+    pub fn blocking_send(&self, event: Event) -> Result<(), Closed> {
+        match self.coalesce(event) {
+            Some(queued) => self.sender.blocking_send(queued).map_err(|_| self.closed_error()),
+            None => Ok(()),
+        }
+    }
+
+    /// Queues `event` only if there is room, for events the next one makes redundant.
+    pub fn try_send(&self, event: Event) -> Result<(), Closed> {
+        let Some(queued) = self.coalesce(event) else {
+            return Ok(());
+        };
+        match self.sender.try_send(queued) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(queued)) => {
+                if let Queued::Latest(key) = queued {
+                    self.latest.lock().unwrap().remove(&key);
+                }
+                self.metrics.dropped.fetch_add(1, Ordering::Relaxed);
+                Ok(())
+            }
+            Err(TrySendError::Closed(_)) => Err(self.closed_error()),
+        }
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.sender.is_closed()
+    }
+
+    /// Waits until the app stops receiving events.
+    pub async fn closed(&self) {
+        self.sender.closed().await
+    }
+
+    /// Parks a coalescable event as the latest for its key, only queueing a marker when none
+    /// was waiting already.
+    fn coalesce(&self, event: Event) -> Option<Queued> {
+        let Some(key) = Coalesce::of(&event) else {
+            self.forget(&event);
+            return Some(Queued::Event(event));
+        };
+        if self.latest.lock().unwrap().insert(key, event).is_some() {
+            self.metrics.coalesced.fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+        Some(Queued::Latest(key))
+    }
+
+    /// Drops the parked updates to nodes `event` removes, which would otherwise be applied after it.
+    fn forget(&self, event: &Event) {
+        let Event::UpdatePipeWireState(event) = event else {
+            return;
+        };
+        let mut latest = self.latest.lock().unwrap();
+        let parked = latest.len();
+        match event {
+            PipeWireEvent::RemoveNode(id) => latest.retain(|key, _| key.id() != *id),
+            // Nodes get new ids after a restart, nothing parked refers to a live one
+            PipeWireEvent::Disconnected => latest.clear(),
+            _ => return,
+        }
+        let forgotten = (parked - latest.len()) as u64;
+        self.metrics.coalesced.fetch_add(forgotten, Ordering::Relaxed);
+    }
+
+    fn resolve(&self, queued: Queued) -> Option<Event> {
+        match queued {
+            Queued::Event(event) => Some(event),
+            Queued::Latest(key) => self.latest.lock().unwrap().remove(&key),
+        }
+    }
+
+    fn closed_error(&self) -> Closed {
+        self.metrics.dropped.fetch_add(1, Ordering::Relaxed);
+        Closed
+    }
+}
+
 #[derive(Debug)]
 pub struct EventHandler {
     /// Event sender channel.
-    sender: mpsc::UnboundedSender<Event>,
+    sender: EventSender,
     /// Event receiver channel.
-    receiver: mpsc::UnboundedReceiver<Event>,
+    receiver: mpsc::Receiver<Queued>,
     /// Event handler thread.
     handler: tokio::task::JoinHandle<()>,
+    /// Thread running the PipeWire main loop.
+    pipewire: Option<std::thread::JoinHandle<()>>,
+    /// Tells the PipeWire thread to stop reconnecting.
+    pipewire_quit: Arc<AtomicBool>,
 }
 
 impl EventHandler {
     /// Constructs a new instance of [`EventHandler`].
-    pub fn new(tick_rate: u64, socket: Arc<Mutex<NlRouter>>, mut multicast: NlRouterReceiverHandle<u16, Genlmsghdr<u8, u16>>) -> Self {
-        let tick_rate = Duration::from_millis(tick_rate);
-        let (sender, receiver) = mpsc::unbounded_channel();
+    pub fn new(
+        pipewire_commands: pipewire::channel::Receiver<PipeWireCommand>,
+        clock: Duration,
+    ) -> Self {
+        let (sender, receiver) = EventSender::channel(EVENT_CAPACITY);
+        let pipewire_quit = Arc::new(AtomicBool::new(false));
+        let pipewire = pw_monitor(sender.clone(), pipewire_commands, pipewire_quit.clone());
+        hypr_monitor(sender.clone());
+        network_monitor(sender.clone());
+        battery_monitor(sender.clone());
+        bluez_monitor(sender.clone());
+        Self::spawn(sender, receiver, Some(pipewire), pipewire_quit, clock)
+    }
+
+    /// Constructs an [`EventHandler`] fed by the synthetic sources of `kbar --demo`.
+    pub fn demo(clock: Duration) -> Self {
+        let (sender, receiver) = EventSender::channel(EVENT_CAPACITY);
+        demo_monitor(sender.clone());
+        Self::spawn(sender, receiver, None, Arc::default(), clock)
+    }
+
+    /// Starts the task forwarding clock ticks and terminal input.
+    ///
+    /// The clock ticks whenever the wall clock reaches a multiple of `clock_tick`.
+    fn spawn(
+        sender: EventSender,
+        receiver: mpsc::Receiver<Queued>,
+        pipewire: Option<std::thread::JoinHandle<()>>,
+        pipewire_quit: Arc<AtomicBool>,
+        clock_tick: Duration,
+    ) -> Self {
         let _sender = sender.clone();
-        pw_monitor(sender.clone());
         let handler = tokio::spawn(async move {
-            let mut tick = tokio::time::interval(tick_rate);
-            let mut hypr_reader = hyprland::event_listener::EventStream::new();
-            let mut net_reader = network::eventstream::EventStream::new(socket, multicast);
+            let clock = tokio::time::sleep(until_next(clock_tick));
+            tokio::pin!(clock);
+            let mut term_reader = crossterm::event::EventStream::new();
             loop {
-                let tick_delay = tick.tick();
-                let hyprland_event = hypr_reader.next().fuse();
-                let net_event = net_reader.next().fuse();
+                let term_event = term_reader.next().fuse();
                 tokio::select! {
                     _ = _sender.closed() => {
                         break;
                     }
-                    _ = tick_delay => {
-                        _sender.send(Event::Tick).unwrap();
-                        _sender.send(Event::Render).unwrap();
-                    }
-                    Some(Ok(evt)) = hyprland_event => {
-                        _sender.send(Event::UpdateHyprlandState(evt)).unwrap();
+                    () = &mut clock => {
+                        clock.as_mut().reset(tokio::time::Instant::now() + until_next(clock_tick));
+                        if _sender.try_send(Event::Tick(Timer::Clock)).is_err() {
+                            break;
+                        }
                     }
-                    Some(Ok(evt)) = net_event => {
-                        _sender.send(Event::UpdateNetworkState(evt)).unwrap();
+                    Some(Ok(evt)) = term_event => {
+                        let event = match evt {
+                            CrosstermEvent::Key(key) if key.kind == KeyEventKind::Press => {
+                                Event::Key(key)
+                            }
+                            CrosstermEvent::Mouse(mouse) => Event::Mouse(mouse),
+                            CrosstermEvent::Resize(x, y) => Event::Resize(x, y),
+                            _ => continue,
+                        };
+                        if _sender.send(event).await.is_err() {
+                            break;
+                        }
                     }
                 }
             }
@@ -83,6 +318,35 @@ impl EventHandler {
             sender,
             receiver,
             handler,
+            pipewire,
+            pipewire_quit,
+        }
+    }
+
+    /// Stops the PipeWire thread and waits for it to finish.
+    ///
+    /// A connected session also has to be sent `PipeWireCommand::Quit` to leave its main loop, one
+    /// that is waiting to reconnect stops on its own. Stops receiving events first, so the thread
+    /// is not left waiting for room in the queue.
+    pub fn join_pipewire(&mut self) {
+        self.pipewire_quit.store(true, Ordering::Relaxed);
+        self.receiver.close();
+        if let Some(pipewire) = self.pipewire.take() {
+            let _ = pipewire.join();
+        }
+    }
+
+    pub fn metrics(&self) -> &EventMetrics {
+        &self.sender.metrics
+    }
+
+    /// Takes an event that is already queued, without waiting.
+    pub fn try_next(&mut self) -> Option<Event> {
+        loop {
+            let queued = self.receiver.try_recv().ok()?;
+            if let Some(event) = self.sender.resolve(queued) {
+                return Some(event);
+            }
         }
     }
 
@@ -91,12 +355,127 @@ impl EventHandler {
     /// This function will always block the current thread if
     /// there is no data available and it's possible for more data to be sent.
     pub async fn next(&mut self) -> AppResult<Event> {
-        self.receiver
-            .recv()
-            .await
-            .ok_or(Box::new(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                "This is an IO error",
-            )))
+        loop {
+            let queued = self
+                .receiver
+                .recv()
+                .await
+                .ok_or(Box::new(std::io::Error::new(
+                    std::io::ErrorKind::Other,
+                    "This is an IO error",
+                )))?;
+            if let Some(event) = self.sender.resolve(queued) {
+                return Ok(event);
+            }
+        }
+    }
+}
+
+/// Time left until the wall clock reaches the next multiple of `period`, e.g. the next minute.
+fn until_next(period: Duration) -> Duration {
+    let period = period.as_millis().max(1) as i64;
+    let millis = chrono::Utc::now().timestamp_millis().rem_euclid(period);
+    Duration::from_millis((period - millis) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipemon::{Session, reconnect};
+
+    fn volumes(id: u32, volume: f32) -> Event {
+        Event::UpdatePipeWireState(PipeWireEvent::UpdateVolumes(id, vec![volume]))
+    }
+
+    fn receive(sender: &EventSender, receiver: &mut mpsc::Receiver<Queued>) -> Vec<Event> {
+        let mut events = Vec::new();
+        while let Ok(queued) = receiver.try_recv() {
+            events.extend(sender.resolve(queued));
+        }
+        events
+    }
+
+    #[test]
+    fn keeps_only_the_latest_volume_per_node() {
+        let (sender, mut receiver) = EventSender::channel(8);
+        for volume in [0.1, 0.2, 0.3] {
+            sender.blocking_send(volumes(1, volume)).unwrap();
+        }
+        sender.blocking_send(volumes(2, 0.5)).unwrap();
+        let events = receive(&sender, &mut receiver);
+        assert!(matches!(
+            events.as_slice(),
+            [
+                Event::UpdatePipeWireState(PipeWireEvent::UpdateVolumes(1, first)),
+                Event::UpdatePipeWireState(PipeWireEvent::UpdateVolumes(2, _)),
+            ] if first == &vec![0.3]
+        ));
+        assert_eq!(sender.metrics.coalesced(), 2);
+    }
+
+    #[test]
+    fn drops_updates_to_removed_nodes() {
+        let (sender, mut receiver) = EventSender::channel(8);
+        sender.blocking_send(volumes(1, 0.5)).unwrap();
+        sender
+            .blocking_send(Event::UpdatePipeWireState(PipeWireEvent::UpdateMuted(1, true)))
+            .unwrap();
+        sender.blocking_send(volumes(2, 0.5)).unwrap();
+        sender
+            .blocking_send(Event::UpdatePipeWireState(PipeWireEvent::RemoveNode(1)))
+            .unwrap();
+        let events = receive(&sender, &mut receiver);
+        assert!(matches!(
+            events.as_slice(),
+            [
+                Event::UpdatePipeWireState(PipeWireEvent::UpdateVolumes(2, _)),
+                Event::UpdatePipeWireState(PipeWireEvent::RemoveNode(1)),
+            ]
+        ));
+        assert_eq!(sender.metrics.coalesced(), 2);
+    }
+
+    #[test]
+    fn drops_ticks_when_full() {
+        let (sender, mut receiver) = EventSender::channel(1);
+        sender.try_send(Event::Tick(Timer::Clock)).unwrap();
+        sender.try_send(Event::Tick(Timer::Marquee)).unwrap();
+        assert_eq!(receive(&sender, &mut receiver).len(), 1);
+        assert_eq!(sender.metrics.dropped(), 1);
+    }
+
+    #[test]
+    fn retries_pipewire_until_quit() {
+        let (sender, mut receiver) = EventSender::channel(8);
+        let (_commands, pipewire_commands) = pipewire::channel::channel();
+        let quit = AtomicBool::new(false);
+        let mut sessions = 0;
+        // The first session loses a running daemon, the second finds none and the bar quits
+        reconnect(
+            &sender,
+            pipewire_commands,
+            || quit.load(Ordering::Relaxed),
+            |_, commands| {
+                sessions += 1;
+                quit.store(sessions == 2, Ordering::Relaxed);
+                Session::Lost {
+                    commands,
+                    connected: sessions == 1,
+                }
+            },
+        );
+        assert_eq!(sessions, 2);
+        assert!(matches!(
+            receive(&sender, &mut receiver).as_slice(),
+            [Event::UpdatePipeWireState(PipeWireEvent::Disconnected)]
+        ));
+    }
+
+    #[test]
+    fn reports_a_closed_app() {
+        let (sender, receiver) = EventSender::channel(1);
+        drop(receiver);
+        assert!(sender.blocking_send(volumes(1, 0.5)).is_err());
+        assert!(sender.try_send(Event::Tick(Timer::Clock)).is_err());
     }
 }
@@ -0,0 +1,395 @@
+use std::borrow::Cow;
+use std::sync::LazyLock;
+
+use ratatui::{
+    layout::Alignment,
+    style::Style,
+    text::{Line, Span},
+};
+use serde::Deserialize;
+use unicode_width::UnicodeWidthStr;
+
+use crate::batterywidget::BatteryWidget;
+use crate::hyprlandwidget::{
+    HyprlandLayoutWidget, HyprlandSubmapWidget, HyprlandWindowWidget, HyprlandWorkSpaceWidget,
+    truncate,
+};
+use crate::networkwidget::NetworkWidget;
+use crate::pipewirewidget::{PipewireWidget, PrivacyWidget};
+
+/// A widget's text, parsed from a template like `{icon} {volume}%`.
+///
+/// - `{name}` is replaced with a variable, the widget documents which it has.
+/// - `{name:>4}` pads it to 4 cells, aligned right. `<` aligns left, the default, `^` centers.
+/// - `{name:.12}` cuts it down to 12 cells, ending it with an ellipsis. Both combine, `{name:>4.12}`.
+/// - `[ {battery}%]` is only shown when every variable in it is non-empty. Sections nest.
+/// - A backslash makes the next character literal, `\{` or `\[`. TOML wants it doubled in
+///   double-quoted strings, not in single-quoted ones.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Format {
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    Text(String),
+    Variable(Variable),
+    Section(Vec<Part>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Variable {
+    name: String,
+    alignment: Alignment,
+    width: usize,
+    max_width: Option<usize>,
+}
+
+/// The variables a widget fills a `Format` with.
+#[derive(Debug, Default)]
+pub struct Values<'a> {
+    values: Vec<(&'static str, Cow<'a, str>, Style)>,
+}
+
+impl<'a> Values<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set(self, name: &'static str, value: impl Into<Cow<'a, str>>) -> Self {
+        self.styled(name, value, Style::new())
+    }
+
+    /// Sets a variable drawn in its own style, on top of the widget's.
+    pub fn styled(
+        mut self,
+        name: &'static str,
+        value: impl Into<Cow<'a, str>>,
+        style: Style,
+    ) -> Self {
+        self.values.push((name, value.into(), style));
+        self
+    }
+
+    fn get(&self, name: &str) -> Option<(&str, Style)> {
+        self.values
+            .iter()
+            .find(|(known, _, _)| *known == name)
+            .map(|(_, value, style)| (value.as_ref(), *style))
+    }
+}
+
+impl Format {
+    /// Parses `template`, which may only use the given variables.
+    pub fn parse(template: &str, variables: &[&str]) -> Result<Self, String> {
+        let mut chars = template.chars().peekable();
+        let parts = parse_parts(&mut chars, variables, false)?;
+        Ok(Self { parts })
+    }
+
+    /// The text for `values`, in the styles they were given.
+    pub fn line(&self, values: &Values) -> Line<'static> {
+        let mut spans = Vec::new();
+        render(&self.parts, values, &mut spans);
+        Line::from(spans)
+    }
+
+    /// The text for `values`, without styles.
+    pub fn text(&self, values: &Values) -> String {
+        self.line(values)
+            .spans
+            .into_iter()
+            .map(|span| span.content)
+            .collect()
+    }
+}
+
+type Chars<'a> = std::iter::Peekable<std::str::Chars<'a>>;
+
+fn parse_parts(
+    chars: &mut Chars,
+    variables: &[&str],
+    in_section: bool,
+) -> Result<Vec<Part>, String> {
+    let mut parts = Vec::new();
+    let mut text = String::new();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            text.push(chars.next().unwrap_or(c));
+            continue;
+        }
+        if !matches!(c, '{' | '}' | '[' | ']') {
+            text.push(c);
+            continue;
+        }
+        if !text.is_empty() {
+            parts.push(Part::Text(std::mem::take(&mut text)));
+        }
+        match c {
+            '{' => {
+                let mut spec = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => spec.push(c),
+                        None => return Err(format!("unclosed \"{{{}\"", spec)),
+                    }
+                }
+                parts.push(Part::Variable(parse_variable(&spec, variables)?));
+            }
+            '[' => parts.push(Part::Section(parse_parts(chars, variables, true)?)),
+            ']' if in_section => return Ok(parts),
+            _ => return Err(format!("unmatched \"{}\", write \"\\{}\" for the character", c, c)),
+        }
+    }
+    if in_section {
+        return Err("unclosed \"[\"".to_string());
+    }
+    if !text.is_empty() {
+        parts.push(Part::Text(text));
+    }
+    Ok(parts)
+}
+
+/// Parses the inside of `{name:>4.12}`.
+fn parse_variable(spec: &str, variables: &[&str]) -> Result<Variable, String> {
+    let (name, options) = spec.split_once(':').unwrap_or((spec, ""));
+    if !variables.contains(&name) {
+        return Err(format!(
+            "unknown variable \"{}\", expected one of {}",
+            name,
+            variables.join(", ")
+        ));
+    }
+    let (alignment, options) = match options.chars().next() {
+        Some('<') => (Alignment::Left, &options[1..]),
+        Some('>') => (Alignment::Right, &options[1..]),
+        Some('^') => (Alignment::Center, &options[1..]),
+        _ => (Alignment::Left, options),
+    };
+    let (width, max_width) = match options.split_once('.') {
+        Some((width, max_width)) => (width, Some(max_width)),
+        None => (options, None),
+    };
+    let number = |digits: &str| {
+        digits
+            .parse::<usize>()
+            .map_err(|_| format!("bad width in \"{{{}}}\"", spec))
+    };
+    Ok(Variable {
+        name: name.to_string(),
+        alignment,
+        width: match width {
+            "" => 0,
+            width => number(width)?,
+        },
+        max_width: max_width.map(number).transpose()?,
+    })
+}
+
+/// Appends the spans of `parts` to `spans`, returning whether all their variables had a value.
+fn render(parts: &[Part], values: &Values, spans: &mut Vec<Span<'static>>) -> bool {
+    let mut complete = true;
+    for part in parts {
+        match part {
+            Part::Text(text) => spans.push(Span::raw(text.clone())),
+            Part::Variable(variable) => {
+                let (value, style) = values.get(&variable.name).unwrap_or(("", Style::new()));
+                complete &= !value.is_empty();
+                spans.push(Span::styled(variable.fit(value), style));
+            }
+            Part::Section(parts) => {
+                let mut section = Vec::new();
+                if render(parts, values, &mut section) {
+                    spans.extend(section);
+                }
+            }
+        }
+    }
+    complete
+}
+
+impl Variable {
+    fn fit(&self, value: &str) -> String {
+        let value = match self.max_width {
+            Some(max_width) => truncate(value, max_width),
+            None => value.to_string(),
+        };
+        let padding = self.width.saturating_sub(value.width());
+        match self.alignment {
+            Alignment::Left => format!("{}{}", value, " ".repeat(padding)),
+            Alignment::Right => format!("{}{}", " ".repeat(padding), value),
+            Alignment::Center => format!(
+                "{}{}{}",
+                " ".repeat(padding / 2),
+                value,
+                " ".repeat(padding - padding / 2)
+            ),
+        }
+    }
+}
+
+/// The text of every widget, from the config's `[format]` section.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "FormatsConfig")]
+pub struct Formats {
+    pub workspaces: Format,
+    pub window: Format,
+    pub submap: Format,
+    pub layout: Format,
+    pub network: Format,
+    pub privacy: Format,
+    pub volume: Format,
+    pub battery: Format,
+}
+
+static DEFAULT: LazyLock<Formats> = LazyLock::new(Formats::default);
+
+impl Formats {
+    /// The formats kbar always had, for widgets not given any.
+    pub fn shared() -> &'static Formats {
+        &DEFAULT
+    }
+
+    /// Builds the formats a config's `[format]` section describes.
+    pub fn from_config(config: &FormatsConfig) -> Result<Self, String> {
+        let parse = |name: &str, template: &Option<String>, default: &str, variables: &[&str]| {
+            Format::parse(template.as_deref().unwrap_or(default), variables)
+                .map_err(|error| format!("format of {}: {}", name, error))
+        };
+        Ok(Self {
+            workspaces: parse(
+                "workspaces",
+                &config.workspaces,
+                HyprlandWorkSpaceWidget::FORMAT,
+                HyprlandWorkSpaceWidget::VARIABLES,
+            )?,
+            window: parse(
+                "window",
+                &config.window,
+                HyprlandWindowWidget::FORMAT,
+                HyprlandWindowWidget::VARIABLES,
+            )?,
+            submap: parse(
+                "submap",
+                &config.submap,
+                HyprlandSubmapWidget::FORMAT,
+                HyprlandSubmapWidget::VARIABLES,
+            )?,
+            layout: parse(
+                "layout",
+                &config.layout,
+                HyprlandLayoutWidget::FORMAT,
+                HyprlandLayoutWidget::VARIABLES,
+            )?,
+            network: parse(
+                "network",
+                &config.network,
+                NetworkWidget::FORMAT,
+                NetworkWidget::VARIABLES,
+            )?,
+            privacy: parse(
+                "privacy",
+                &config.privacy,
+                PrivacyWidget::FORMAT,
+                PrivacyWidget::VARIABLES,
+            )?,
+            volume: parse(
+                "volume",
+                &config.volume,
+                PipewireWidget::FORMAT,
+                PipewireWidget::VARIABLES,
+            )?,
+            battery: parse(
+                "battery",
+                &config.battery,
+                BatteryWidget::FORMAT,
+                BatteryWidget::VARIABLES,
+            )?,
+        })
+    }
+}
+
+impl Default for Formats {
+    fn default() -> Self {
+        Self::from_config(&FormatsConfig::default()).expect("default formats parse")
+    }
+}
+
+impl TryFrom<FormatsConfig> for Formats {
+    type Error = String;
+
+    fn try_from(config: FormatsConfig) -> Result<Self, String> {
+        Self::from_config(&config)
+    }
+}
+
+/// The `[format]` section of the config file, unset widgets keep their format.
+///
+/// ```toml
+/// [format]
+/// network = "{icon} {ssid} {signal_dbm}dBm"
+/// volume = "{icon} {volume:>3}%[ {battery_icon}{battery}%]"
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FormatsConfig {
+    pub workspaces: Option<String>,
+    pub window: Option<String>,
+    pub submap: Option<String>,
+    pub layout: Option<String>,
+    pub network: Option<String>,
+    pub privacy: Option<String>,
+    pub volume: Option<String>,
+    pub battery: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use ratatui::style::Stylize;
+
+    use super::*;
+
+    const VARIABLES: &[&str] = &["icon", "volume", "battery"];
+
+    fn text(template: &str, values: Values) -> String {
+        Format::parse(template, VARIABLES).unwrap().text(&values)
+    }
+
+    #[test]
+    fn fills_in_variables() {
+        let values = || Values::new().set("icon", "V").set("volume", "7");
+        assert_eq!(text("{icon} {volume}%", values()), "V 7%");
+        assert_eq!(text("{volume:>3}|{volume:3}|{volume:^3}", values()), "  7|7  | 7 ");
+        assert_eq!(text(r"{icon:.1}\{{volume}\} \[x\]\\", values()), r"V{7} [x]\");
+        assert_eq!(
+            text("{icon}[ {battery}%]", values().set("battery", "")),
+            "V"
+        );
+        assert_eq!(
+            text("{icon}[ {battery}%[ {volume}]]", values().set("battery", "90")),
+            "V 90% 7"
+        );
+        assert_eq!(
+            text("{icon:.3}", Values::new().set("icon", "speakers")),
+            "sp…"
+        );
+    }
+
+    #[test]
+    fn keeps_variable_styles() {
+        let format = Format::parse("{icon} {volume}", VARIABLES).unwrap();
+        let bold = Style::new().bold();
+        let line = format.line(&Values::new().set("icon", "V").styled("volume", "7", bold));
+        assert_eq!(line.spans[2], Span::styled("7", bold));
+    }
+
+    #[test]
+    fn rejects_bad_templates() {
+        for template in ["{icons}", "{volume", "volume}", "[{volume}", "{volume}]", "{volume:>x}"] {
+            assert!(Format::parse(template, VARIABLES).is_err(), "{}", template);
+        }
+        assert!(toml::from_str::<Formats>("network = \"{ssid} {signal_dbm}dBm\"").is_ok());
+        assert!(toml::from_str::<Formats>("clock = \"{time}\"").is_err());
+    }
+}
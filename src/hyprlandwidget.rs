@@ -1,72 +1,1098 @@
+use std::collections::HashMap;
+
 use ratatui::{
     buffer::Buffer,
-    layout::Rect,
-    style::Color,
-    widgets::{StatefulWidget, Tabs, Widget},
+    layout::{Alignment, Rect},
+    style::Style,
+    text::Line,
+    widgets::{Paragraph, StatefulWidget, Tabs, Widget},
 };
 
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
 use hyprland::{
-    data::{Clients, Workspaces},
-    shared::HyprData,
+    data::{Clients, Devices, FullscreenMode, Monitors, Workspace, Workspaces},
+    shared::{Address, HyprData, HyprDataActive},
 };
 
-#[derive(Debug, Clone)]
+use serde::{Deserialize, Serialize};
+
+use crate::format::{Format, Formats, Values};
+use crate::hyprmon::HyprlandEvent;
+use crate::icons::Icons;
+use crate::theme::Theme;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HyprlandWorkspace {
+    pub id: i32,
+    pub name: String,
+    pub monitor: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HyprlandClient {
+    pub address: Address,
+    pub workspace: i32,
+    pub class: String,
+    pub fullscreen: bool,
+    pub floating: bool,
+    pub pinned: bool,
+    /// Asked for attention and not focused since.
+    pub urgent: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ActiveWindow {
+    pub address: Option<Address>,
+    pub class: String,
+    pub title: String,
+}
+
+/// What the bar knows about Hyprland, a snapshot of it is sent whenever Hyprland is reached.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct HyprlandState {
-    pub workspaces: Vec<(i32, String)>,
-    // activeworkspace: String,
-    pub activeworkspaceindex: usize,
-    pub activewindow: String,
+    /// Workspaces sorted by id.
+    pub workspaces: Vec<HyprlandWorkspace>,
+    pub activeworkspace: i32,
+    /// Only show workspaces on this monitor, set from the config's `monitor` or `KBAR_MONITOR`.
+    pub monitor: Option<String>,
+    pub focused_monitor: String,
+    /// The workspace shown on each monitor.
+    pub visible_workspaces: HashMap<String, i32>,
+    pub activewindow: ActiveWindow,
+    /// Scroll position of the active window title when it is shown as a marquee.
+    pub title_scroll: usize,
+    pub clients: Vec<HyprlandClient>,
+    pub submap: String,
+    pub keyboard_layout: String,
+    /// Whether the Hyprland event stream is currently connected.
+    pub connected: bool,
 }
 
 impl HyprlandState {
-    pub fn new() -> Self {
-        let hyprworkspaces = Workspaces::get().unwrap();
+    /// Builds the state from a fresh snapshot of Hyprland's workspaces, clients, monitors and devices.
+    pub fn load() -> hyprland::Result<Self> {
+        let hyprworkspaces = Workspaces::get()?;
         let mut workspaces = hyprworkspaces
             .iter()
-            .map(|workspace| (workspace.id, workspace.name.clone()))
-            .collect::<Vec<(i32, String)>>();
-        workspaces.sort_by(|a, b| a.0.cmp(&b.0));
-        let clients = Clients::get().unwrap();
+            .map(|workspace| HyprlandWorkspace {
+                id: workspace.id,
+                name: workspace.name.clone(),
+                monitor: workspace.monitor.clone(),
+            })
+            .collect::<Vec<HyprlandWorkspace>>();
+        workspaces.sort_by_key(|workspace| workspace.id);
+        let activeworkspace = Workspace::get_active()?.id;
+        let monitors = Monitors::get()?;
+        let focused_monitor = match monitors.iter().find(|monitor| monitor.focused) {
+            Some(monitor) => monitor.name.clone(),
+            None => "".to_string(),
+        };
+        let clients = Clients::get()?;
         let activewindow = clients.iter().find(|&x| x.focus_history_id == 0);
+        let devices = Devices::get()?;
+        let keyboard = devices
+            .keyboards
+            .iter()
+            .find(|keyboard| keyboard.main)
+            .or(devices.keyboards.first());
 
-        Self {
+        Ok(Self {
             workspaces,
-            activeworkspaceindex: 0,
+            activeworkspace,
+            monitor: None,
+            focused_monitor,
+            visible_workspaces: monitors
+                .iter()
+                .map(|monitor| (monitor.name.clone(), monitor.active_workspace.id))
+                .collect(),
             activewindow: match activewindow {
-                Some(client) => client.title.to_string(),
+                Some(client) => ActiveWindow {
+                    address: Some(client.address.clone()),
+                    class: client.class.clone(),
+                    title: client.title.clone(),
+                },
+                None => ActiveWindow::default(),
+            },
+            title_scroll: 0,
+            clients: clients
+                .iter()
+                .map(|client| HyprlandClient {
+                    address: client.address.clone(),
+                    workspace: client.workspace.id,
+                    class: client.class.clone(),
+                    fullscreen: client.fullscreen != FullscreenMode::None,
+                    floating: client.floating,
+                    pinned: client.pinned,
+                    urgent: false,
+                })
+                .collect(),
+            submap: "".to_string(),
+            keyboard_layout: match keyboard {
+                Some(keyboard) => keyboard.active_keymap.clone(),
                 None => "".to_string(),
             },
+            connected: true,
+        })
+    }
+
+    /// Applies an event, returning whether it changed anything.
+    pub fn update(&mut self, event: HyprlandEvent) -> bool {
+        let before = self.clone();
+        match event {
+            HyprlandEvent::WorkspaceChanged { id } => {
+                self.activeworkspace = id;
+                self.visible_workspaces
+                    .insert(self.focused_monitor.clone(), id);
+            }
+            HyprlandEvent::WorkspaceDeleted { id } => {
+                if let Ok(pos) = self
+                    .workspaces
+                    .binary_search_by_key(&id, |workspace| workspace.id)
+                {
+                    self.workspaces.remove(pos);
+                }
+            }
+            HyprlandEvent::WorkspaceAdded { id, name } => {
+                if let Err(pos) = self
+                    .workspaces
+                    .binary_search_by_key(&id, |workspace| workspace.id)
+                {
+                    // createworkspace does not say where, new workspaces open on the focused monitor
+                    self.workspaces.insert(
+                        pos,
+                        HyprlandWorkspace {
+                            id,
+                            name,
+                            monitor: self.focused_monitor.clone(),
+                        },
+                    );
+                }
+            }
+            HyprlandEvent::WorkspaceRenamed { id, name } => {
+                if let Some(workspace) = self.workspace_mut(id) {
+                    workspace.name = name;
+                }
+            }
+            HyprlandEvent::WorkspaceMoved { id, monitor } => {
+                if let Some(workspace) = self.workspace_mut(id) {
+                    workspace.monitor = monitor.clone();
+                }
+                self.visible_workspaces.retain(|_, visible| *visible != id);
+                // The focused workspace stays focused when moved, now showing on the other monitor
+                if id == self.activeworkspace {
+                    self.focused_monitor = monitor.clone();
+                    self.visible_workspaces.insert(monitor, id);
+                }
+            }
+            HyprlandEvent::WindowOpened {
+                address,
+                workspace,
+                class,
+            } => {
+                self.window_opened(address, &workspace, class);
+            }
+            HyprlandEvent::WindowClosed { address } => {
+                self.window_closed(&address);
+            }
+            HyprlandEvent::WindowMoved { address, workspace } => {
+                self.window_moved(&address, workspace);
+            }
+            HyprlandEvent::ActiveWindowChanged {
+                address,
+                class,
+                title,
+            } => self.active_window_changed(ActiveWindow {
+                address,
+                class,
+                title,
+            }),
+            HyprlandEvent::WindowTitleChanged { address, title } => {
+                self.window_title_changed(&address, title);
+            }
+            HyprlandEvent::ActiveMonitorChanged { monitor, workspace } => {
+                self.focused_monitor = monitor.clone();
+                // focusedmon only names the workspace, resolve it to the id everything else uses
+                let workspace = workspace.and_then(|name| {
+                    self.workspaces
+                        .iter()
+                        .find(|workspace| workspace.name == name)
+                });
+                if let Some(workspace) = workspace {
+                    self.activeworkspace = workspace.id;
+                    self.visible_workspaces.insert(monitor, workspace.id);
+                }
+            }
+            HyprlandEvent::FullscreenStateChanged(fullscreen) => {
+                self.fullscreen_changed(fullscreen);
+            }
+            HyprlandEvent::FloatStateChanged { address, floating } => {
+                self.floating_changed(&address, floating);
+            }
+            HyprlandEvent::WindowPinned { address, pinned } => {
+                self.pinned_changed(&address, pinned);
+            }
+            HyprlandEvent::WindowUrgent { address } => {
+                self.window_urgent(&address);
+            }
+            HyprlandEvent::SubMapChanged(submap) => {
+                self.submap = submap;
+            }
+            HyprlandEvent::LayoutChanged(layout) => {
+                self.keyboard_layout = layout;
+            }
+        }
+        *self != before
+    }
+
+    fn workspace_mut(&mut self, id: i32) -> Option<&mut HyprlandWorkspace> {
+        self.workspaces
+            .iter_mut()
+            .find(|workspace| workspace.id == id)
+    }
+
+    /// The workspaces this bar shows, those on `monitor` if it is set.
+    pub fn shown_workspaces(&self) -> Vec<&HyprlandWorkspace> {
+        self.workspaces
+            .iter()
+            .filter(|workspace| match &self.monitor {
+                Some(monitor) => &workspace.monitor == monitor,
+                None => true,
+            })
+            .collect()
+    }
+
+    /// Position of the active workspace in `shown_workspaces`, which is the tab to highlight.
+    /// With a monitor set, that is the workspace showing on it even while another one is focused.
+    pub fn active_index(&self) -> Option<usize> {
+        let active = match &self.monitor {
+            Some(monitor) => *self.visible_workspaces.get(monitor)?,
+            None => self.activeworkspace,
+        };
+        self.shown_workspaces()
+            .iter()
+            .position(|workspace| workspace.id == active)
+    }
+
+    /// Scrolls the active window title by one column.
+    pub fn tick(&mut self) {
+        self.title_scroll = self.title_scroll.wrapping_add(1);
+    }
+
+    pub fn active_window_changed(&mut self, activewindow: ActiveWindow) {
+        if let Some(client) = activewindow
+            .address
+            .clone()
+            .and_then(|address| self.client_mut(&address))
+        {
+            client.urgent = false;
+        }
+        self.activewindow = activewindow;
+        self.title_scroll = 0;
+    }
+
+    pub fn window_title_changed(&mut self, address: &Address, title: String) {
+        if self.activewindow.address.as_ref() == Some(address) && self.activewindow.title != title {
+            self.activewindow.title = title;
+            self.title_scroll = 0;
+        }
+    }
+
+    pub fn window_opened(&mut self, address: Address, workspace_name: &str, class: String) {
+        // openwindow only carries the workspace name, so resolve it to an id
+        let workspace = match self
+            .workspaces
+            .iter()
+            .find(|workspace| workspace.name == workspace_name)
+        {
+            Some(workspace) => workspace.id,
+            None => match workspace_name.parse::<i32>() {
+                Ok(id) => id,
+                Err(_) => return,
+            },
+        };
+        self.window_closed(&address);
+        self.clients.push(HyprlandClient {
+            address,
+            workspace,
+            class,
+            fullscreen: false,
+            floating: false,
+            pinned: false,
+            urgent: false,
+        });
+    }
+
+    pub fn window_urgent(&mut self, address: &Address) {
+        // Hyprland also flags windows that are already focused, those need no attention
+        if self.activewindow.address.as_ref() == Some(address) {
+            return;
+        }
+        if let Some(client) = self.client_mut(address) {
+            client.urgent = true;
         }
     }
+
+    pub fn window_closed(&mut self, address: &Address) {
+        self.clients.retain(|client| &client.address != address);
+    }
+
+    pub fn window_moved(&mut self, address: &Address, workspace: i32) {
+        if let Some(client) = self.client_mut(address) {
+            client.workspace = workspace;
+        }
+    }
+
+    fn client_mut(&mut self, address: &Address) -> Option<&mut HyprlandClient> {
+        self.clients
+            .iter_mut()
+            .find(|client| &client.address == address)
+    }
+
+    pub fn active_client(&self) -> Option<&HyprlandClient> {
+        let address = self.activewindow.address.as_ref()?;
+        self.clients.iter().find(|client| &client.address == address)
+    }
+
+    /// The fullscreen event carries no window, it always refers to the focused one.
+    ///
+    /// Only one window per workspace can be fullscreen, so the others on its workspace never are.
+    pub fn fullscreen_changed(&mut self, fullscreen: bool) {
+        let Some(active) = self.active_client() else {
+            return;
+        };
+        let (address, workspace) = (active.address.clone(), active.workspace);
+        for client in self.clients.iter_mut() {
+            if client.address == address {
+                client.fullscreen = fullscreen;
+            } else if client.workspace == workspace {
+                client.fullscreen = false;
+            }
+        }
+    }
+
+    pub fn floating_changed(&mut self, address: &Address, floating: bool) {
+        if let Some(client) = self.client_mut(address) {
+            client.floating = floating;
+        }
+    }
+
+    pub fn pinned_changed(&mut self, address: &Address, pinned: bool) {
+        if let Some(client) = self.client_mut(address) {
+            client.pinned = pinned;
+        }
+    }
+
+    pub fn has_fullscreen(&self, workspace: i32) -> bool {
+        self.clients
+            .iter()
+            .any(|client| client.workspace == workspace && client.fullscreen)
+    }
+
+    /// Whether a window on the workspace asked for attention.
+    pub fn is_urgent(&self, workspace: i32) -> bool {
+        self.clients
+            .iter()
+            .any(|client| client.workspace == workspace && client.urgent)
+    }
+
+    pub fn window_count(&self, workspace: i32) -> usize {
+        self.clients
+            .iter()
+            .filter(|client| client.workspace == workspace)
+            .count()
+    }
+
+    /// Classes of the windows on a workspace, without duplicates, in the order they were opened.
+    pub fn window_classes(&self, workspace: i32) -> Vec<&str> {
+        let mut classes: Vec<&str> = Vec::new();
+        for client in &self.clients {
+            if client.workspace == workspace && !classes.contains(&client.class.as_str()) {
+                classes.push(&client.class);
+            }
+        }
+        classes
+    }
+}
+
+/// Shows a tab for each workspace, highlighting the active one.
+///
+/// Format variables: `name`, `count` of windows, the `icons` of their classes and `fullscreen`,
+/// an icon when one of them is fullscreen.
+pub struct HyprlandWorkSpaceWidget<'a> {
+    format: &'a Format,
+    icons: &'a Icons,
+    active_style: Style,
+    empty_style: Style,
+    urgent_style: Style,
+    disconnected_style: Style,
 }
 
-pub struct HyprlandWorkSpaceWidget {}
+impl<'a> HyprlandWorkSpaceWidget<'a> {
+    pub const FORMAT: &'static str = "{name} {icons}[ {fullscreen}]";
+    pub const VARIABLES: &'static [&'static str] = &["name", "count", "icons", "fullscreen"];
 
-impl<'a> HyprlandWorkSpaceWidget {
     pub fn new() -> Self {
-        Self {}
+        let theme = Theme::default();
+        Self {
+            format: &Formats::shared().workspaces,
+            icons: Icons::shared(),
+            active_style: theme.workspace_active,
+            empty_style: theme.workspace_empty,
+            urgent_style: theme.workspace_urgent,
+            disconnected_style: theme.disconnected,
+        }
+    }
+
+    pub fn theme(&mut self, theme: &Theme) {
+        self.active_style = theme.workspace_active;
+        self.empty_style = theme.workspace_empty;
+        self.urgent_style = theme.workspace_urgent;
+        self.disconnected_style = theme.disconnected;
+    }
+
+    pub fn icons(&mut self, icons: &'a Icons) {
+        self.icons = icons;
+    }
+
+    pub fn format(&mut self, format: &'a Format) {
+        self.format = format;
+    }
+
+    fn tab_title(&self, state: &HyprlandState, id: i32, name: &str) -> String {
+        let icons = state
+            .window_classes(id)
+            .iter()
+            .map(|class| self.icons.app(class))
+            .collect::<Vec<&str>>()
+            .join(" ");
+        let fullscreen = match state.has_fullscreen(id) {
+            true => self.icons.fullscreen.as_str(),
+            false => "",
+        };
+        let values = Values::new()
+            .set("name", name)
+            .set("count", state.window_count(id).to_string())
+            .set("icons", icons)
+            .set("fullscreen", fullscreen);
+        format!(" {} ", self.format.text(&values).trim())
+    }
+
+    /// A tab for each shown workspace, styled when it is urgent or empty.
+    fn tabs(&self, state: &HyprlandState) -> Vec<Line<'static>> {
+        state
+            .shown_workspaces()
+            .iter()
+            .map(|workspace| {
+                let title = self.tab_title(state, workspace.id, &workspace.name);
+                if state.is_urgent(workspace.id) {
+                    Line::styled(title, self.urgent_style)
+                } else if state.window_count(workspace.id) == 0 {
+                    Line::styled(title, self.empty_style)
+                } else {
+                    Line::raw(title)
+                }
+            })
+            .collect()
+    }
+
+    fn disconnected_text(&self) -> String {
+        format!(" {} Hyprland disconnected ", self.icons.hyprland_disconnected)
+    }
+
+    /// Width needed to show every tab, or the notice while Hyprland is disconnected.
+    pub fn width(&self, state: &HyprlandState) -> u16 {
+        match state.connected {
+            true => tabs_width(&self.tabs(state)),
+            false => self.disconnected_text().width() as u16,
+        }
+    }
+
+    /// Width of the active tab alone, what is kept when the bar runs short of room.
+    pub fn min_width(&self, state: &HyprlandState) -> u16 {
+        if !state.connected {
+            return self.width(state);
+        }
+        match state.active_index() {
+            Some(active) => tabs_width(&self.tabs(state)[active..=active]),
+            None => 0,
+        }
     }
 }
 
-impl StatefulWidget for HyprlandWorkSpaceWidget {
+impl StatefulWidget for HyprlandWorkSpaceWidget<'_> {
     type State = HyprlandState;
     fn render(self, area: Rect, buf: &mut Buffer, state: &mut HyprlandState) {
-        let highlight_style = (Color::Black, Color::Blue);
-        let workspace_num = state.workspaces.len()-1;
-        Tabs::new(
-            state
-                .workspaces
-                .iter()
-                .map(|workspace| format!(" {} ", workspace.1))
-                .collect::<Vec<String>>(),
-        )
-        .padding(" ", " ")
-        .highlight_style(highlight_style)
-        .select(if state.activeworkspaceindex > workspace_num {
-            workspace_num
+        if !state.connected {
+            Paragraph::new(self.disconnected_text())
+                .style(self.disconnected_style)
+                .render(area, buf);
+            return;
+        }
+        let tabs = self.tabs(state);
+        let selected = state.active_index();
+        // Scroll the tabs when they do not all fit, so that the active one stays in view
+        let first = match selected {
+            Some(selected) => (0..selected)
+                .find(|&first| tabs_width(&tabs[first..=selected]) <= area.width)
+                .unwrap_or(selected),
+            None => 0,
+        };
+        Tabs::new(tabs.into_iter().skip(first))
+            .padding(" ", " ")
+            .highlight_style(self.active_style)
+            .select(selected.map(|selected| selected - first))
+            .render(area, buf);
+    }
+}
+
+/// Width of `tabs` drawn side by side with their padding and the dividers between them.
+fn tabs_width(tabs: &[Line]) -> u16 {
+    let titles: usize = tabs.iter().map(|tab| tab.width() + 2).sum();
+    (titles + tabs.len().saturating_sub(1)) as u16
+}
+
+/// Cuts `text` down to `width` terminal cells, ending it with an ellipsis if anything was removed.
+pub fn truncate(text: &str, width: usize) -> String {
+    let text_width: usize = text.chars().map(|c| c.width().unwrap_or(0)).sum();
+    if text_width <= width {
+        return text.to_string();
+    }
+    if width == 0 {
+        return "".to_string();
+    }
+    let mut truncated = String::new();
+    let mut used = 0;
+    for c in text.chars() {
+        let char_width = c.width().unwrap_or(0);
+        if used + char_width > width - 1 {
+            break;
+        }
+        used += char_width;
+        truncated.push(c);
+    }
+    truncated.push('…');
+    truncated
+}
+
+/// A `width` cell wide window into `text` repeated endlessly, starting `offset` characters in.
+pub fn marquee(text: &str, width: usize, offset: usize) -> String {
+    let looped = format!("{}   ", text).chars().collect::<Vec<char>>();
+    let mut window = String::new();
+    let mut used = 0;
+    for c in looped.iter().cycle().skip(offset % looped.len()) {
+        let char_width = c.width().unwrap_or(0);
+        if used + char_width > width {
+            break;
+        }
+        used += char_width;
+        window.push(*c);
+    }
+    window
+}
+
+/// Shows the active window, truncated or scrolled to fit, behind indicators for whether it is
+/// fullscreen, floating or pinned.
+///
+/// Format variables: `class` and `title`.
+pub struct HyprlandWindowWidget<'a> {
+    alignment: Alignment,
+    icons: &'a Icons,
+    format: &'a Format,
+    marquee: bool,
+}
+
+impl<'a> HyprlandWindowWidget<'a> {
+    pub const FORMAT: &'static str = "{class}: {title}";
+    pub const VARIABLES: &'static [&'static str] = &["class", "title"];
+
+    pub fn new() -> Self {
+        Self {
+            alignment: Alignment::Center,
+            icons: Icons::shared(),
+            format: &Formats::shared().window,
+            marquee: false,
+        }
+    }
+
+    pub fn icons(&mut self, icons: &'a Icons) {
+        self.icons = icons;
+    }
+
+    pub fn format(&mut self, format: &'a Format) {
+        self.format = format;
+    }
+
+    /// Scrolls titles that do not fit instead of truncating them.
+    pub fn marquee(&mut self) {
+        self.marquee = true;
+    }
+
+    /// Whether the title is scrolling in `width` columns, so needs redrawing as time passes.
+    pub fn scrolling(&self, state: &HyprlandState, width: u16) -> bool {
+        if !self.marquee || !state.connected {
+            return false;
+        }
+        let (indicators, text) = self.text(state);
+        text.width() + indicators.width() > width as usize
+    }
+
+    /// The indicators and the formatted title, before fitting them in the available width.
+    fn text(&self, state: &HyprlandState) -> (String, String) {
+        let mut indicators = String::new();
+        if let Some(client) = state.active_client() {
+            for (set, icon) in [
+                (client.fullscreen, &self.icons.fullscreen),
+                (client.floating, &self.icons.floating),
+                (client.pinned, &self.icons.pinned),
+            ] {
+                if set {
+                    indicators.push_str(icon);
+                    indicators.push(' ');
+                }
+            }
+        }
+        let values = Values::new()
+            .set("class", state.activewindow.class.as_str())
+            .set("title", state.activewindow.title.as_str());
+        let text = self.format.text(&values);
+        (indicators, text)
+    }
+}
+
+impl Default for HyprlandWindowWidget<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StatefulWidget for HyprlandWindowWidget<'_> {
+    type State = HyprlandState;
+    fn render(self, area: Rect, buf: &mut Buffer, state: &mut HyprlandState) {
+        // The last known window is stale while Hyprland is disconnected
+        if !state.connected
+            || state.activewindow.address.is_none() && state.activewindow.title.is_empty()
+        {
+            return;
+        }
+        let (indicators, text) = self.text(state);
+        let width = (area.width as usize).saturating_sub(indicators.width());
+        let text = if text.width() <= width {
+            text
+        } else if self.marquee {
+            marquee(&text, width, state.title_scroll)
         } else {
-            state.activeworkspaceindex
+            truncate(&text, width)
+        };
+        Paragraph::new(indicators + &text)
+            .alignment(self.alignment)
+            .render(area, buf);
+    }
+}
+
+/// Shows the active submap (binding mode), and nothing while in the default one.
+///
+/// Format variables: `submap`.
+pub struct HyprlandSubmapWidget<'a> {
+    alignment: Alignment,
+    format: &'a Format,
+    style: Style,
+}
+
+impl<'a> HyprlandSubmapWidget<'a> {
+    pub const FORMAT: &'static str = " {submap} ";
+    pub const VARIABLES: &'static [&'static str] = &["submap"];
+
+    pub fn new() -> Self {
+        Self {
+            alignment: Alignment::Center,
+            format: &Formats::shared().submap,
+            style: Theme::default().submap,
+        }
+    }
+
+    pub fn theme(&mut self, theme: &Theme) {
+        self.style = theme.submap;
+    }
+
+    pub fn format(&mut self, format: &'a Format) {
+        self.format = format;
+    }
+
+    /// Width needed to show the current submap, zero when none is active.
+    pub fn width(&self, state: &HyprlandState) -> u16 {
+        match state.submap.is_empty() {
+            true => 0,
+            false => self.text(state).width() as u16,
+        }
+    }
+
+    fn text(&self, state: &HyprlandState) -> String {
+        self.format
+            .text(&Values::new().set("submap", state.submap.as_str()))
+    }
+}
+
+impl Default for HyprlandSubmapWidget<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StatefulWidget for HyprlandSubmapWidget<'_> {
+    type State = HyprlandState;
+    fn render(self, area: Rect, buf: &mut Buffer, state: &mut HyprlandState) {
+        if state.submap.is_empty() {
+            return;
+        }
+        Paragraph::new(self.text(state))
+            .style(self.style)
+            .alignment(self.alignment)
+            .render(area, buf);
+    }
+}
+
+/// Shows the active keyboard layout.
+///
+/// Format variables: `layout`, a short name like "US" for "English (US)", and `name`, the full one.
+pub struct HyprlandLayoutWidget<'a> {
+    alignment: Alignment,
+    format: &'a Format,
+}
+
+impl<'a> HyprlandLayoutWidget<'a> {
+    pub const FORMAT: &'static str = "{layout}";
+    pub const VARIABLES: &'static [&'static str] = &["layout", "name"];
+
+    pub fn new() -> Self {
+        Self {
+            alignment: Alignment::Center,
+            format: &Formats::shared().layout,
+        }
+    }
+
+    pub fn format(&mut self, format: &'a Format) {
+        self.format = format;
+    }
+
+    pub fn short_name(layout: &str) -> String {
+        // Prefer a short variant like "English (US)" -> "US", otherwise the language prefix
+        if let (Some(start), Some(end)) = (layout.find('('), layout.rfind(')')) {
+            // A stray ")" before the "(" leaves no variant between them
+            let variant = layout.get(start + 1..end).unwrap_or("");
+            if !variant.is_empty() && variant.chars().count() <= 3 {
+                return variant.to_uppercase();
+            }
+        }
+        layout.chars().take(2).collect::<String>().to_uppercase()
+    }
+
+    /// Width needed to show the layout.
+    pub fn width(&self, state: &HyprlandState) -> u16 {
+        self.line(state).width() as u16
+    }
+
+    fn line(&self, state: &HyprlandState) -> Line<'static> {
+        let values = Values::new()
+            .set("layout", HyprlandLayoutWidget::short_name(&state.keyboard_layout))
+            .set("name", state.keyboard_layout.as_str());
+        self.format.line(&values)
+    }
+}
+
+impl Default for HyprlandLayoutWidget<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StatefulWidget for HyprlandLayoutWidget<'_> {
+    type State = HyprlandState;
+    fn render(self, area: Rect, buf: &mut Buffer, state: &mut HyprlandState) {
+        Paragraph::new(self.line(state))
+            .alignment(self.alignment)
+            .render(area, buf);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyprland::event_listener::{
+        Event as IpcEvent, MonitorEventData, NonSpecialWorkspaceEventData, WorkspaceEventData,
+        WorkspaceMovedEventData,
+    };
+    use hyprland::shared::WorkspaceType;
+
+    fn state(workspaces: &[(i32, &str)], activeworkspace: i32) -> HyprlandState {
+        HyprlandState {
+            workspaces: workspaces
+                .iter()
+                .map(|&(id, name)| HyprlandWorkspace {
+                    id,
+                    name: name.to_string(),
+                    monitor: "eDP-1".to_string(),
+                })
+                .collect(),
+            activeworkspace,
+            focused_monitor: "eDP-1".to_string(),
+            visible_workspaces: HashMap::from([("eDP-1".to_string(), activeworkspace)]),
+            connected: true,
+            ..Default::default()
+        }
+    }
+
+    fn moved(id: i32, monitor: &str) -> IpcEvent {
+        IpcEvent::WorkspaceMoved(WorkspaceMovedEventData {
+            name: WorkspaceType::Regular(id.to_string()),
+            id,
+            monitor: monitor.to_string(),
         })
-        .render(area, buf);
+    }
+
+    fn names(state: &HyprlandState) -> Vec<&str> {
+        state
+            .shown_workspaces()
+            .iter()
+            .map(|workspace| workspace.name.as_str())
+            .collect()
+    }
+
+    fn workspace(id: i32, name: &str) -> WorkspaceEventData {
+        WorkspaceEventData {
+            name: WorkspaceType::Regular(name.to_string()),
+            id,
+        }
+    }
+
+    /// Feeds events as they come from the socket, so the conversion is exercised too.
+    fn replay(state: &mut HyprlandState, events: Vec<IpcEvent>) {
+        for event in events {
+            state.update(HyprlandEvent::from_ipc(event).unwrap());
+        }
+    }
+
+    fn highlighted(state: &HyprlandState) -> Option<&str> {
+        state
+            .active_index()
+            .map(|index| state.shown_workspaces()[index].name.as_str())
+    }
+
+    #[test]
+    fn workspace_change_highlights_by_id() {
+        let mut state = state(&[(1, "1"), (2, "2"), (3, "3")], 1);
+        replay(
+            &mut state,
+            vec![IpcEvent::WorkspaceChanged(workspace(3, "3"))],
+        );
+        assert_eq!(highlighted(&state), Some("3"));
+    }
+
+    #[test]
+    fn adding_workspace_before_active_keeps_highlight() {
+        let mut state = state(&[(2, "2"), (3, "3")], 3);
+        replay(
+            &mut state,
+            vec![IpcEvent::WorkspaceAdded(workspace(1, "1"))],
+        );
+        assert_eq!(state.active_index(), Some(2));
+        assert_eq!(highlighted(&state), Some("3"));
+    }
+
+    #[test]
+    fn deleting_workspace_before_active_keeps_highlight() {
+        let mut state = state(&[(1, "1"), (2, "2"), (3, "3")], 3);
+        replay(
+            &mut state,
+            vec![IpcEvent::WorkspaceDeleted(workspace(1, "1"))],
+        );
+        assert_eq!(state.active_index(), Some(1));
+        assert_eq!(highlighted(&state), Some("3"));
+    }
+
+    #[test]
+    fn switching_to_new_workspace_and_back() {
+        let mut state = state(&[(1, "1")], 1);
+        replay(
+            &mut state,
+            vec![
+                IpcEvent::WorkspaceAdded(workspace(4, "4")),
+                IpcEvent::WorkspaceChanged(workspace(4, "4")),
+                IpcEvent::WorkspaceChanged(workspace(1, "1")),
+                IpcEvent::WorkspaceDeleted(workspace(4, "4")),
+            ],
+        );
+        assert_eq!(state.workspaces.len(), 1);
+        assert_eq!(highlighted(&state), Some("1"));
+    }
+
+    #[test]
+    fn named_workspaces_sort_by_id() {
+        let mut state = state(&[(1, "1"), (2, "2")], 2);
+        replay(
+            &mut state,
+            vec![
+                IpcEvent::WorkspaceAdded(workspace(-1337, "web")),
+                IpcEvent::WorkspaceChanged(workspace(-1337, "web")),
+            ],
+        );
+        assert_eq!(state.active_index(), Some(0));
+        assert_eq!(highlighted(&state), Some("web"));
+    }
+
+    #[test]
+    fn monitor_focus_highlights_its_workspace() {
+        let mut state = state(&[(1, "1"), (2, "2"), (5, "5")], 1);
+        replay(
+            &mut state,
+            vec![IpcEvent::ActiveMonitorChanged(MonitorEventData {
+                monitor_name: "HDMI-A-1".to_string(),
+                workspace_name: Some(WorkspaceType::Regular("5".to_string())),
+            })],
+        );
+        assert_eq!(highlighted(&state), Some("5"));
+    }
+
+    #[test]
+    fn monitor_focus_on_unknown_workspace_keeps_highlight() {
+        let mut state = state(&[(1, "1"), (2, "2")], 2);
+        replay(
+            &mut state,
+            vec![IpcEvent::ActiveMonitorChanged(MonitorEventData {
+                monitor_name: "HDMI-A-1".to_string(),
+                workspace_name: None,
+            })],
+        );
+        assert_eq!(highlighted(&state), Some("2"));
+    }
+
+    #[test]
+    fn rename_keeps_highlight() {
+        let mut state = state(&[(1, "1"), (2, "2")], 2);
+        replay(
+            &mut state,
+            vec![IpcEvent::WorkspaceRenamed(NonSpecialWorkspaceEventData {
+                name: "code".to_string(),
+                id: 2,
+            })],
+        );
+        assert_eq!(state.active_index(), Some(1));
+        assert_eq!(highlighted(&state), Some("code"));
+        assert_eq!(names(&state), vec!["1", "code"]);
+    }
+
+    #[test]
+    fn move_to_monitor_keeps_highlight() {
+        let mut state = state(&[(1, "1"), (2, "2")], 1);
+        replay(&mut state, vec![moved(2, "HDMI-A-1")]);
+        assert_eq!(highlighted(&state), Some("1"));
+    }
+
+    #[test]
+    fn moved_workspace_changes_bars() {
+        let mut laptop = state(&[(1, "1"), (2, "2")], 1);
+        laptop.monitor = Some("eDP-1".to_string());
+        let mut external = laptop.clone();
+        external.monitor = Some("HDMI-A-1".to_string());
+        for state in [&mut laptop, &mut external] {
+            replay(state, vec![moved(2, "HDMI-A-1")]);
+        }
+        assert_eq!(names(&laptop), vec!["1"]);
+        assert_eq!(names(&external), vec!["2"]);
+    }
+
+    #[test]
+    fn moving_focused_workspace_follows_it() {
+        let mut external = state(&[(1, "1"), (2, "2")], 2);
+        external.monitor = Some("HDMI-A-1".to_string());
+        replay(&mut external, vec![moved(2, "HDMI-A-1")]);
+        assert_eq!(names(&external), vec!["2"]);
+        assert_eq!(highlighted(&external), Some("2"));
+        assert_eq!(external.focused_monitor, "HDMI-A-1");
+    }
+
+    #[test]
+    fn monitor_bar_highlights_its_own_workspace() {
+        let mut laptop = state(&[(1, "1"), (2, "2")], 1);
+        laptop.monitor = Some("eDP-1".to_string());
+        replay(
+            &mut laptop,
+            vec![
+                moved(2, "HDMI-A-1"),
+                IpcEvent::ActiveMonitorChanged(MonitorEventData {
+                    monitor_name: "HDMI-A-1".to_string(),
+                    workspace_name: Some(WorkspaceType::Regular("2".to_string())),
+                }),
+                IpcEvent::WorkspaceAdded(workspace(3, "3")),
+                IpcEvent::WorkspaceChanged(workspace(3, "3")),
+            ],
+        );
+        assert_eq!(laptop.activeworkspace, 3);
+        assert_eq!(names(&laptop), vec!["1"]);
+        assert_eq!(highlighted(&laptop), Some("1"));
+    }
+
+    #[test]
+    fn urgent_until_focused() {
+        let mut state = state(&[(1, "1"), (2, "2")], 1);
+        let window = |workspace: &str| {
+            IpcEvent::WindowOpened(hyprland::event_listener::WindowOpenEvent {
+                window_address: Address::new("0xa"),
+                workspace_name: workspace.to_string(),
+                window_class: "Slack".to_string(),
+                window_title: "Slack".to_string(),
+            })
+        };
+        replay(
+            &mut state,
+            vec![window("2"), IpcEvent::UrgentStateChanged(Address::new("0xa"))],
+        );
+        assert!(state.is_urgent(2));
+        assert!(!state.is_urgent(1));
+        replay(
+            &mut state,
+            vec![
+                IpcEvent::WorkspaceChanged(workspace(2, "2")),
+                IpcEvent::ActiveWindowChanged(Some(hyprland::event_listener::WindowEventData {
+                    class: "Slack".to_string(),
+                    title: "Slack".to_string(),
+                    address: Address::new("0xa"),
+                })),
+            ],
+        );
+        assert!(!state.is_urgent(2));
+    }
+
+    #[test]
+    fn shortens_layout_names() {
+        assert_eq!(HyprlandLayoutWidget::short_name("English (US)"), "US");
+        assert_eq!(HyprlandLayoutWidget::short_name("German"), "GE");
+        assert_eq!(HyprlandLayoutWidget::short_name("foo) (bar"), "FO");
+        assert_eq!(HyprlandLayoutWidget::short_name("()"), "()");
+    }
+
+    #[test]
+    fn one_fullscreen_window_per_workspace() {
+        let mut state = state(&[(1, "1"), (2, "2")], 1);
+        state.clients = [(1, 1), (2, 1), (3, 2)]
+            .into_iter()
+            .map(|(address, workspace)| HyprlandClient {
+                address: Address::new(format!("0x{}", address)),
+                workspace,
+                class: "kitty".to_string(),
+                fullscreen: true,
+                floating: false,
+                pinned: false,
+                urgent: false,
+            })
+            .collect();
+        state.activewindow.address = Some(Address::new("0x1"));
+        state.fullscreen_changed(true);
+        let fullscreen: Vec<bool> = state.clients.iter().map(|client| client.fullscreen).collect();
+        assert_eq!(fullscreen, vec![true, false, true]);
+
+        state.fullscreen_changed(false);
+        assert!(!state.has_fullscreen(1));
+        assert!(state.has_fullscreen(2));
     }
 }
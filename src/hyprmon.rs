@@ -0,0 +1,174 @@
+use std::time::Duration;
+
+use futures::StreamExt;
+use hyprland::{
+    data::Workspaces,
+    event_listener::{Event as IpcEvent, EventStream},
+    shared::{Address, HyprData},
+};
+use serde::{Deserialize, Serialize};
+use crate::event::{Event, EventSender};
+use crate::hyprlandwidget::HyprlandState;
+
+/// The Hyprland events the bar reacts to, as plain data that can be recorded and replayed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum HyprlandEvent {
+    WorkspaceChanged { id: i32 },
+    WorkspaceAdded { id: i32, name: String },
+    WorkspaceDeleted { id: i32 },
+    WorkspaceRenamed { id: i32, name: String },
+    WorkspaceMoved { id: i32, monitor: String },
+    /// Only the workspace name is known when a window opens.
+    WindowOpened {
+        address: Address,
+        workspace: String,
+        class: String,
+    },
+    WindowClosed { address: Address },
+    WindowMoved { address: Address, workspace: i32 },
+    /// No address means no window is focused.
+    ActiveWindowChanged {
+        address: Option<Address>,
+        class: String,
+        title: String,
+    },
+    WindowTitleChanged { address: Address, title: String },
+    ActiveMonitorChanged {
+        monitor: String,
+        workspace: Option<String>,
+    },
+    FullscreenStateChanged(bool),
+    FloatStateChanged { address: Address, floating: bool },
+    WindowPinned { address: Address, pinned: bool },
+    /// A window asked for attention.
+    WindowUrgent { address: Address },
+    SubMapChanged(String),
+    LayoutChanged(String),
+}
+
+impl HyprlandEvent {
+    /// Converts an event from the Hyprland socket, `None` for the ones the bar ignores.
+    pub fn from_ipc(event: IpcEvent) -> Option<Self> {
+        Some(match event {
+            IpcEvent::WorkspaceChanged(data) => HyprlandEvent::WorkspaceChanged { id: data.id },
+            IpcEvent::WorkspaceAdded(data) => HyprlandEvent::WorkspaceAdded {
+                id: data.id,
+                name: data.name.to_string(),
+            },
+            IpcEvent::WorkspaceDeleted(data) => HyprlandEvent::WorkspaceDeleted { id: data.id },
+            IpcEvent::WorkspaceRenamed(data) => HyprlandEvent::WorkspaceRenamed {
+                id: data.id,
+                name: data.name,
+            },
+            IpcEvent::WorkspaceMoved(data) => HyprlandEvent::WorkspaceMoved {
+                id: data.id,
+                monitor: data.monitor,
+            },
+            IpcEvent::WindowOpened(data) => HyprlandEvent::WindowOpened {
+                address: data.window_address,
+                workspace: data.workspace_name,
+                class: data.window_class,
+            },
+            IpcEvent::WindowClosed(address) => HyprlandEvent::WindowClosed { address },
+            IpcEvent::WindowMoved(data) => HyprlandEvent::WindowMoved {
+                address: data.window_address,
+                workspace: data.workspace_id,
+            },
+            IpcEvent::ActiveWindowChanged(data) => match data {
+                Some(data) => HyprlandEvent::ActiveWindowChanged {
+                    address: Some(data.address),
+                    class: data.class,
+                    title: data.title,
+                },
+                None => HyprlandEvent::ActiveWindowChanged {
+                    address: None,
+                    class: "".to_string(),
+                    title: "".to_string(),
+                },
+            },
+            IpcEvent::WindowTitleChanged(data) => HyprlandEvent::WindowTitleChanged {
+                address: data.address,
+                title: data.title,
+            },
+            IpcEvent::ActiveMonitorChanged(data) => HyprlandEvent::ActiveMonitorChanged {
+                monitor: data.monitor_name,
+                workspace: data.workspace_name.map(|workspace| workspace.to_string()),
+            },
+            IpcEvent::FullscreenStateChanged(fullscreen) => {
+                HyprlandEvent::FullscreenStateChanged(fullscreen)
+            }
+            IpcEvent::FloatStateChanged(data) => HyprlandEvent::FloatStateChanged {
+                address: data.address,
+                floating: data.floating,
+            },
+            IpcEvent::WindowPinned(data) => HyprlandEvent::WindowPinned {
+                address: data.address,
+                pinned: data.pinned,
+            },
+            IpcEvent::UrgentStateChanged(address) => HyprlandEvent::WindowUrgent { address },
+            IpcEvent::SubMapChanged(submap) => HyprlandEvent::SubMapChanged(submap),
+            IpcEvent::LayoutChanged(data) => HyprlandEvent::LayoutChanged(data.layout_name),
+            _ => return None,
+        })
+    }
+}
+
+const MIN_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+/// How many times a snapshot is tried once Hyprland answers, `MIN_BACKOFF` apart.
+const SNAPSHOT_ATTEMPTS: usize = 3;
+
+/// Forwards Hyprland events to `sender`, reconnecting with exponential backoff whenever the
+/// event socket closes or errors.
+///
+/// `Event::HyprlandConnected` is sent each time Hyprland answers requests again, with a snapshot
+/// of its state so the state can be re-seeded, and `Event::HyprlandDisconnected` each time the
+/// event stream is lost.
+pub fn hypr_monitor(sender: EventSender) {
+    tokio::spawn(async move {
+        let mut backoff = MIN_BACKOFF;
+        loop {
+            let reachable = tokio::task::spawn_blocking(|| Workspaces::get().is_ok())
+                .await
+                .unwrap_or(false);
+            if !reachable {
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+                continue;
+            }
+            backoff = MIN_BACKOFF;
+            let snapshot = snapshot().await.map(Box::new);
+            if sender.send(Event::HyprlandConnected(snapshot)).await.is_err() {
+                return;
+            }
+
+            let mut hypr_reader = EventStream::new();
+            while let Some(Ok(evt)) = hypr_reader.next().await {
+                let Some(evt) = HyprlandEvent::from_ipc(evt) else {
+                    continue;
+                };
+                if sender.send(Event::UpdateHyprlandState(evt)).await.is_err() {
+                    return;
+                }
+            }
+
+            if sender.send(Event::HyprlandDisconnected).await.is_err() {
+                return;
+            }
+            tokio::time::sleep(backoff).await;
+        }
+    });
+}
+
+/// Loads Hyprland's state, trying again shortly when one of its requests fails.
+async fn snapshot() -> Option<HyprlandState> {
+    for attempt in 0..SNAPSHOT_ATTEMPTS {
+        if attempt > 0 {
+            tokio::time::sleep(MIN_BACKOFF).await;
+        }
+        if let Ok(Ok(state)) = tokio::task::spawn_blocking(HyprlandState::load).await {
+            return Some(state);
+        }
+    }
+    None
+}
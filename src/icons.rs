@@ -0,0 +1,354 @@
+use std::collections::HashMap;
+use std::sync::LazyLock;
+
+use serde::Deserialize;
+
+use crate::pipewirewidget::SinkKind;
+
+/// The glyphs widgets draw, from one of the presets with overrides from the config.
+///
+/// Level icons go from empty to full, each covering a fixed step: 10% of battery, 25% of wifi
+/// signal and 34% of volume.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "IconsConfig")]
+pub struct Icons {
+    pub battery_charging: Vec<String>,
+    pub battery_discharging: Vec<String>,
+    pub wifi: Vec<String>,
+    pub wifi_disconnected: String,
+    pub volume: Vec<String>,
+    pub muted: String,
+    /// In place of the volume while PipeWire is unreachable.
+    pub audio_disconnected: String,
+    /// In front of the number of streams playing.
+    pub playing: String,
+    /// The default sink's channels are at different volumes.
+    pub unbalanced: String,
+    pub bluetooth_battery: String,
+    pub headphones: String,
+    pub speakers: String,
+    pub hdmi: String,
+    /// Marks the default output in the sinks popup.
+    pub default_sink: String,
+    pub gauge_filled: String,
+    pub gauge_empty: String,
+    pub microphone: String,
+    pub camera: String,
+    pub hyprland_disconnected: String,
+    /// Windows whose class has no icon in `apps`.
+    pub window: String,
+    pub fullscreen: String,
+    pub floating: String,
+    pub pinned: String,
+    /// Icons for window classes, keyed by the lowercase class.
+    pub apps: HashMap<String, String>,
+}
+
+static NERD: LazyLock<Icons> = LazyLock::new(Icons::nerd);
+
+impl Icons {
+    /// The Nerd Font icons kbar always used, for widgets not given any.
+    pub fn shared() -> &'static Icons {
+        &NERD
+    }
+
+    /// Nerd Font glyphs, the terminal needs a patched font.
+    pub fn nerd() -> Self {
+        Self {
+            battery_charging: strings(&[
+                "󰢟", "󰢜", "󰂆", "󰂇", "󰂈", "󰢝", "󰂉", "󰢞", "󰂊", "󰂋", "󰁹",
+            ]),
+            battery_discharging: strings(&[
+                "󰂎", "󰁺", "󰁻", "󰁼", "󰁽", "󰁾", "󰁿", "󰂀", "󰂁", "󰂂", "󰁹",
+            ]),
+            wifi: strings(&["󰤯", "󰤟", "󰤢", "󰤥", "󰤨"]),
+            wifi_disconnected: "󰤮".to_string(),
+            volume: strings(&["󰕿", "󰖀", "󰕾"]),
+            muted: "󰝟".to_string(),
+            audio_disconnected: "󰖁".to_string(),
+            playing: "󰝚".to_string(),
+            unbalanced: "󰗑".to_string(),
+            bluetooth_battery: "󰂯".to_string(),
+            headphones: "󰋋".to_string(),
+            speakers: "󰓃".to_string(),
+            hdmi: "󰡁".to_string(),
+            default_sink: "●".to_string(),
+            gauge_filled: "█".to_string(),
+            gauge_empty: "░".to_string(),
+            microphone: "󰍬".to_string(),
+            camera: "󰍹".to_string(),
+            hyprland_disconnected: "󰖪".to_string(),
+            window: "󰖯".to_string(),
+            fullscreen: "󰊓".to_string(),
+            floating: "󰖲".to_string(),
+            pinned: "󰐃".to_string(),
+            apps: apps(&[
+                ("firefox", "󰈹"),
+                ("chromium", "󰊯"),
+                ("google-chrome", "󰊯"),
+                ("kitty", "󰆍"),
+                ("alacritty", "󰆍"),
+                ("foot", "󰆍"),
+                ("code", "󰨞"),
+                ("discord", "󰙯"),
+                ("spotify", "󰓇"),
+                ("thunderbird", "󰇮"),
+                ("org.gnome.nautilus", "󰉋"),
+            ]),
+        }
+    }
+
+    /// Emoji and symbols any font with decent Unicode coverage has.
+    pub fn unicode() -> Self {
+        Self {
+            battery_charging: strings(&["🔌"; 11]),
+            battery_discharging: strings(&[
+                "🪫", "🪫", "🔋", "🔋", "🔋", "🔋", "🔋", "🔋", "🔋", "🔋", "🔋",
+            ]),
+            wifi: strings(&["▁", "▂", "▂▄", "▂▄▆", "▂▄▆█"]),
+            wifi_disconnected: "✗".to_string(),
+            volume: strings(&["🔈", "🔉", "🔊"]),
+            muted: "🔇".to_string(),
+            audio_disconnected: "🔇".to_string(),
+            playing: "♪".to_string(),
+            unbalanced: "⚖".to_string(),
+            bluetooth_battery: "ᛒ".to_string(),
+            headphones: "🎧".to_string(),
+            speakers: "📢".to_string(),
+            hdmi: "📺".to_string(),
+            default_sink: "●".to_string(),
+            gauge_filled: "█".to_string(),
+            gauge_empty: "░".to_string(),
+            microphone: "🎤".to_string(),
+            camera: "📷".to_string(),
+            hyprland_disconnected: "⚠".to_string(),
+            window: "□".to_string(),
+            fullscreen: "⛶".to_string(),
+            floating: "◇".to_string(),
+            pinned: "📌".to_string(),
+            apps: apps(&[
+                ("firefox", "🦊"),
+                ("chromium", "🌐"),
+                ("google-chrome", "🌐"),
+                ("discord", "💬"),
+                ("spotify", "🎵"),
+                ("thunderbird", "✉"),
+                ("org.gnome.nautilus", "📁"),
+            ]),
+        }
+    }
+
+    /// Plain ASCII, for the Linux console and anything else.
+    pub fn ascii() -> Self {
+        Self {
+            battery_charging: strings(&["CHG"; 11]),
+            battery_discharging: strings(&["BAT"; 11]),
+            wifi: strings(&["W0", "W1", "W2", "W3", "W4"]),
+            wifi_disconnected: "W!".to_string(),
+            volume: strings(&["VOL"; 3]),
+            muted: "MUTE".to_string(),
+            audio_disconnected: "VOL".to_string(),
+            playing: ">".to_string(),
+            unbalanced: "~".to_string(),
+            bluetooth_battery: "BT".to_string(),
+            headphones: "HP".to_string(),
+            speakers: "SPK".to_string(),
+            hdmi: "HDMI".to_string(),
+            default_sink: "*".to_string(),
+            gauge_filled: "#".to_string(),
+            gauge_empty: "-".to_string(),
+            microphone: "MIC".to_string(),
+            camera: "CAM".to_string(),
+            hyprland_disconnected: "!".to_string(),
+            window: "o".to_string(),
+            fullscreen: "[F]".to_string(),
+            floating: "[f]".to_string(),
+            pinned: "[p]".to_string(),
+            apps: HashMap::new(),
+        }
+    }
+
+    /// The icon of `levels` for `value`, each icon covering `step` of it.
+    pub fn level(levels: &[String], value: usize, step: usize) -> &str {
+        levels
+            .get(value / step)
+            .or(levels.last())
+            .map_or("", String::as_str)
+    }
+
+    pub fn sink(&self, kind: SinkKind) -> &str {
+        match kind {
+            SinkKind::Headphones => &self.headphones,
+            SinkKind::Speakers => &self.speakers,
+            SinkKind::Hdmi => &self.hdmi,
+        }
+    }
+
+    /// The icon for a window class, matched case-insensitively.
+    pub fn app(&self, class: &str) -> &str {
+        match self.apps.get(&class.to_lowercase()) {
+            Some(icon) => icon,
+            None => &self.window,
+        }
+    }
+
+    /// Builds the icons a config's `[icons]` section describes.
+    pub fn from_config(config: &IconsConfig) -> Result<Self, String> {
+        let mut icons = match config.preset {
+            Preset::Nerd => Self::nerd(),
+            Preset::Unicode => Self::unicode(),
+            Preset::Ascii => Self::ascii(),
+        };
+        for (class, icon) in &config.apps {
+            icons.apps.insert(class.to_lowercase(), icon.clone());
+        }
+        for (name, icon) in &config.icons {
+            match icon {
+                IconConfig::One(icon) => {
+                    let Some(single) = icons.single_mut(name) else {
+                        return Err(format!("unknown icon \"{}\"", name));
+                    };
+                    *single = icon.clone();
+                }
+                IconConfig::Levels(levels) => {
+                    let Some(known) = icons.levels_mut(name) else {
+                        return Err(format!("icon \"{}\" is not a list", name));
+                    };
+                    if levels.len() != known.len() {
+                        return Err(format!("icon \"{}\" needs {} levels", name, known.len()));
+                    }
+                    *known = levels.clone();
+                }
+            }
+        }
+        Ok(icons)
+    }
+
+    fn single_mut(&mut self, name: &str) -> Option<&mut String> {
+        Some(match name {
+            "wifi_disconnected" => &mut self.wifi_disconnected,
+            "muted" => &mut self.muted,
+            "audio_disconnected" => &mut self.audio_disconnected,
+            "playing" => &mut self.playing,
+            "unbalanced" => &mut self.unbalanced,
+            "bluetooth_battery" => &mut self.bluetooth_battery,
+            "headphones" => &mut self.headphones,
+            "speakers" => &mut self.speakers,
+            "hdmi" => &mut self.hdmi,
+            "default_sink" => &mut self.default_sink,
+            "gauge_filled" => &mut self.gauge_filled,
+            "gauge_empty" => &mut self.gauge_empty,
+            "microphone" => &mut self.microphone,
+            "camera" => &mut self.camera,
+            "hyprland_disconnected" => &mut self.hyprland_disconnected,
+            "window" => &mut self.window,
+            "fullscreen" => &mut self.fullscreen,
+            "floating" => &mut self.floating,
+            "pinned" => &mut self.pinned,
+            _ => return None,
+        })
+    }
+
+    fn levels_mut(&mut self, name: &str) -> Option<&mut Vec<String>> {
+        Some(match name {
+            "battery_charging" => &mut self.battery_charging,
+            "battery_discharging" => &mut self.battery_discharging,
+            "wifi" => &mut self.wifi,
+            "volume" => &mut self.volume,
+            _ => return None,
+        })
+    }
+}
+
+impl TryFrom<IconsConfig> for Icons {
+    type Error = String;
+
+    fn try_from(config: IconsConfig) -> Result<Self, String> {
+        Self::from_config(&config)
+    }
+}
+
+impl Default for Icons {
+    fn default() -> Self {
+        Self::nerd()
+    }
+}
+
+fn strings(icons: &[&str]) -> Vec<String> {
+    icons.iter().map(|icon| icon.to_string()).collect()
+}
+
+fn apps(icons: &[(&str, &str)]) -> HashMap<String, String> {
+    icons
+        .iter()
+        .map(|(class, icon)| (class.to_string(), icon.to_string()))
+        .collect()
+}
+
+/// The `[icons]` section of the config file, any other key overrides the icon of that name.
+///
+/// ```toml
+/// [icons]
+/// preset = "unicode"
+/// muted = "M"
+/// wifi = ["w0", "w1", "w2", "w3", "w4"]
+///
+/// [icons.apps]
+/// slack = "S"
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct IconsConfig {
+    pub preset: Preset,
+    pub apps: HashMap<String, String>,
+    #[serde(flatten)]
+    pub icons: HashMap<String, IconConfig>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Preset {
+    #[default]
+    Nerd,
+    Unicode,
+    Ascii,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum IconConfig {
+    One(String),
+    Levels(Vec<String>),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overrides_the_preset() {
+        let icons: Icons = toml::from_str(
+            r#"
+            preset = "ascii"
+            muted = "M"
+            wifi = ["w0", "w1", "w2", "w3", "w4"]
+
+            [apps]
+            Slack = "S"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(icons.muted, "M");
+        assert_eq!(Icons::level(&icons.wifi, 64, 25), "w2");
+        assert_eq!(Icons::level(&icons.wifi, 140, 25), "w4");
+        assert_eq!(icons.app("slack"), "S");
+        assert_eq!(icons.app("firefox"), "o");
+    }
+
+    #[test]
+    fn rejects_bad_overrides() {
+        assert!(toml::from_str::<Icons>("mute = \"M\"").is_err());
+        assert!(toml::from_str::<Icons>("volume = [\"v\"]").is_err());
+        assert!(toml::from_str::<Icons>("preset = \"emoji\"").is_err());
+    }
+}
@@ -1,5 +1,6 @@
 use crate::{
     app::{App, AppResult},
+    config::Config,
 };
 
 pub mod app;
@@ -7,15 +8,60 @@ pub mod tui;
 pub mod ui;
 pub mod event;
 pub mod hyprlandwidget;
+pub mod hyprmon;
+pub mod icons;
 pub mod batterywidget;
+pub mod bluezmon;
+pub mod clockwidget;
+pub mod config;
+pub mod demo;
+pub mod format;
 pub mod pipemon;
 pub mod pipewirewidget;
+pub mod record;
+pub mod theme;
 pub mod network;
 pub mod networkwidget;
+pub mod overlay;
+
+const USAGE: &str = "usage: kbar [--demo | --record FILE | --replay FILE]";
 
 #[tokio::main]
 async fn main() -> AppResult<()> {
-    let mut app = App::new().await;
-    let _result = app.run().await;
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(error) => {
+            eprintln!("kbar: {}", error);
+            std::process::exit(1);
+        }
+    };
+    match args.iter().map(String::as_str).collect::<Vec<_>>().as_slice() {
+        [] => {
+            let mut app = App::new();
+            app.configure(config);
+            let _result = app.run().await;
+        }
+        ["--record", path] => {
+            let mut app = App::new();
+            app.configure(config);
+            app.record_to(path)?;
+            let _result = app.run().await;
+        }
+        ["--demo"] => {
+            let mut app = App::demo();
+            app.configure(config);
+            let _result = app.run().await;
+        }
+        ["--replay", path] => {
+            let mut app = App::offline();
+            app.configure(config);
+            app.replay(path)?;
+        }
+        _ => {
+            eprintln!("{}", USAGE);
+            std::process::exit(2);
+        }
+    }
     Ok(())
 }
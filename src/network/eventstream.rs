@@ -15,7 +15,6 @@ use neli::{
     utils::Groups,
 };
 
-use std::sync::{Arc, Mutex};
 use futures::executor::block_on;
 
 use neli::router::asynchronous::{NlRouter, NlRouterReceiverHandle};
@@ -46,9 +45,9 @@ pub struct EventStream {
 
 #[must_use = "streams nothing unless polled"]
 impl EventStream {
-    pub fn new(socket: Arc<Mutex<NlRouter>>, mut multicast: NlRouterReceiverHandle<u16, Genlmsghdr<u8, u16>>) -> Self {
-        let id = block_on(socket.lock().unwrap().resolve_nl_mcast_group("nl80211", "mlme")).unwrap();
-        socket.lock().unwrap().add_mcast_membership(Groups::new_groups(&[id])).unwrap();
+    pub fn new(socket: &NlRouter, mut multicast: NlRouterReceiverHandle<u16, Genlmsghdr<u8, u16>>) -> Self {
+        let id = block_on(socket.resolve_nl_mcast_group("nl80211", "mlme")).unwrap();
+        socket.add_mcast_membership(Groups::new_groups(&[id])).unwrap();
         let stream = try_stream! {
             loop {
                 if let Some(Ok(msg)) = multicast.next::<GenlId, Genlmsghdr<Nl80211Command, Nl80211Attribute>>().await {
@@ -1,5 +1,7 @@
 use std::io::Cursor;
+use std::time::Duration;
 
+use futures::StreamExt;
 use neli::{
     FromBytesWithInput, ToBytes,
     attr::Attribute,
@@ -13,71 +15,157 @@ use neli::{
 use ratatui::{
     buffer::Buffer,
     layout::{Alignment, Rect},
+    style::Style,
+    text::Line,
     widgets::{Paragraph, StatefulWidget, Widget},
 };
+use serde::{Deserialize, Serialize};
+
+use crate::event::{Event, EventSender};
+use crate::network::eventstream::EventStream;
+use crate::network::nl80211_stream::{
+    Event as NetEvent, Nl80211Attribute, Nl80211Command, Nl80211StaInfo,
+};
+use crate::format::{Format, Formats, Values};
+use crate::icons::Icons;
+use crate::theme::Theme;
 
-use crate::network::nl80211_stream::{Nl80211Attribute, Nl80211Command, Nl80211StaInfo};
-use std::sync::{Arc, Mutex};
 
+/// How often the wifi signal quality is refreshed.
+pub const NETWORK_INTERVAL: Duration = Duration::from_secs(10);
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Connection {
     Connected,
     Disconnected,
 }
 
-#[derive(Clone)]
+/// What the network monitor reports, as plain data that can be recorded and replayed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum NetworkEvent {
+    /// Associated with a network, with the signal strength in dBm when the station reported it.
+    Connected {
+        ssid: String,
+        signal_dbm: Option<i8>,
+    },
+    /// The signal strength in dBm, refreshed while connected.
+    Signal(i8),
+    Disconnected,
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct NetworkState {
-    pub socket: Arc<Mutex<NlRouter>>,
-    pub multicast: Option<Arc<NlRouterReceiverHandle<u16, Genlmsghdr<u8, u16>>>>,
-    ticks: u8,
-    ifindex: u32,
     pub state: Connection,
     pub ssid: String,
-    pub signal: usize,
+    /// Signal strength in dBm as nl80211 reports it, `None` while disconnected.
+    pub signal_dbm: Option<i8>,
+}
+
+impl Default for NetworkState {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl NetworkState {
-    pub async fn new() -> Self {
-        let (socket, mut multicast) = NlRouter::connect(NlFamily::Generic, None, Groups::empty()).await.unwrap();
-        let arc_socket: Arc<Mutex<NlRouter>> = Arc::new(Mutex::new(socket));
-        let ifindex = NetworkState::get_default_ifindex(arc_socket.clone()).await;
-        let mut netstate = Self {
-            socket: arc_socket,
-            multicast: Some(Arc::new(multicast)),
-            ticks: 0,
-            ifindex,
+    pub fn new() -> Self {
+        Self {
             ssid: "Disconnected".to_string(),
             state: Connection::Disconnected,
-            signal: 0,
-        };
-        if netstate.ifindex != 0 {
-            netstate.set_ssid(netstate.ifindex).await;
-            netstate.set_wifi_quality(netstate.ifindex).await;
+            signal_dbm: None,
         }
-        netstate
+    }
+
+    /// Signal quality in percent, from 0% at -100 dBm to 100% at -50 dBm and above.
+    pub fn signal(&self) -> Option<usize> {
+        self.signal_dbm
+            .map(|dbm| (2 * (dbm as i32 + 100)).clamp(0, 100) as usize)
+    }
+
+    /// Applies an event, returning whether it changed anything.
+    pub fn update(&mut self, event: NetworkEvent) -> bool {
+        let before = self.clone();
+        match event {
+            NetworkEvent::Connected { ssid, signal_dbm } => {
+                self.state = Connection::Connected;
+                self.ssid = ssid;
+                self.signal_dbm = signal_dbm;
+            }
+            NetworkEvent::Signal(signal_dbm) => self.signal_dbm = Some(signal_dbm),
+            NetworkEvent::Disconnected => self.disconnected(),
+        }
+        *self != before
     }
 
     pub fn disconnected(&mut self) {
         self.state = Connection::Disconnected;
-        self.signal = 0;
+        self.signal_dbm = None;
         self.ssid = "Disconnected".to_string();
     }
-    pub async fn connected(&mut self, ifindex: u32) {
-        self.state = Connection::Connected;
-        self.set_ssid(ifindex).await;
-        self.set_wifi_quality(ifindex).await;
-    }
+}
 
-    pub async fn tick(&mut self) {
-        self.ticks += 1;
-        if self.ticks >= 100 {
-            self.set_wifi_quality(self.ifindex).await;
-            self.ticks = 0;
+/// Watches the wifi through nl80211, sending its network and signal to `sender`.
+///
+/// The signal is refreshed every `NETWORK_INTERVAL` while connected.
+pub fn network_monitor(sender: EventSender) {
+    tokio::spawn(async move {
+        let (mut wifi, multicast) = Wifi::connect().await;
+        let mut events = EventStream::new(&wifi.socket, multicast);
+        let mut refresh = tokio::time::interval(NETWORK_INTERVAL);
+        let ssid = match wifi.ifindex {
+            0 => None,
+            ifindex => wifi.ssid(ifindex).await,
+        };
+        let mut connected = ssid.is_some();
+        if let Some(ssid) = ssid {
+            let signal_dbm = wifi.signal().await;
+            let event = Event::UpdateNetworkState(NetworkEvent::Connected { ssid, signal_dbm });
+            if sender.send(event).await.is_err() {
+                return;
+            }
+        }
+        loop {
+            let event = tokio::select! {
+                _ = sender.closed() => return,
+                _ = refresh.tick(), if connected => match wifi.signal().await {
+                    Some(signal_dbm) => NetworkEvent::Signal(signal_dbm),
+                    None => continue,
+                },
+                Some(Ok(event)) = events.next() => match event {
+                    NetEvent::Connect(_, Some(ifindex)) => {
+                        connected = true;
+                        let ssid = wifi.ssid(ifindex).await.unwrap_or_default();
+                        NetworkEvent::Connected { ssid, signal_dbm: wifi.signal().await }
+                    }
+                    NetEvent::Disconnect => {
+                        connected = false;
+                        NetworkEvent::Disconnected
+                    }
+                    _ => continue,
+                },
+            };
+            if sender.send(Event::UpdateNetworkState(event)).await.is_err() {
+                return;
+            }
         }
+    });
+}
+
+/// The nl80211 requests the network monitor makes, about the station interface.
+struct Wifi {
+    socket: NlRouter,
+    ifindex: u32,
+}
+
+impl Wifi {
+    async fn connect() -> (Self, NlRouterReceiverHandle<u16, Genlmsghdr<u8, u16>>) {
+        let (socket, multicast) = NlRouter::connect(NlFamily::Generic, None, Groups::empty()).await.unwrap();
+        let ifindex = Wifi::get_default_ifindex(&socket).await;
+        (Self { socket, ifindex }, multicast)
     }
 
-    async fn set_ssid(&mut self, ifindex: u32) {
+    /// The network `ifindex` is associated with, which becomes the interface asked about.
+    async fn ssid(&mut self, ifindex: u32) -> Option<String> {
         self.ifindex = ifindex;
         let attrs = vec![
             NlattrBuilder::<Nl80211Attribute, _>::default()
@@ -90,7 +178,7 @@ impl NetworkState {
         .collect::<GenlBuffer<Nl80211Attribute, neli::types::Buffer>>();
 
         // let (s, _) = NlRouter::connect(NlFamily::Generic, None, Groups::empty()).unwrap();
-        let s = self.socket.lock().unwrap();
+        let s = &self.socket;
         let family_id = s.resolve_genl_family("nl80211").await.unwrap();
         let mut recv = s
             .send::<_, _, u16, Genlmsghdr<Nl80211Command, Nl80211Attribute>>(
@@ -111,22 +199,19 @@ impl NetworkState {
             recv.next().await.unwrap().unwrap();
         let payload = match msg.nl_payload() {
             NlPayload::Payload(p) => p,
-            _ => return,
+            _ => return None,
         };
         let attr_handle = payload.attrs().get_attr_handle();
-        if let Some(attr) = attr_handle.get_attribute(Nl80211Attribute::Ssid) {
-            self.state = Connection::Connected;
-            let payload = attr.payload();
-            let mut buf: Cursor<Vec<u8>> = Cursor::new(Vec::new());
-            let _ = payload.to_bytes(&mut buf);
-            buf.set_position(0);
-            self.ssid = String::from_bytes_with_input(&mut buf, payload.len() + 1).unwrap();
-        }
         // TODO: Connections without SSIDS
+        let attr = attr_handle.get_attribute(Nl80211Attribute::Ssid)?;
+        let payload = attr.payload();
+        let mut buf: Cursor<Vec<u8>> = Cursor::new(Vec::new());
+        let _ = payload.to_bytes(&mut buf);
+        buf.set_position(0);
+        Some(String::from_bytes_with_input(&mut buf, payload.len() + 1).unwrap())
     }
 
-    pub async fn get_default_ifindex(socket: Arc<Mutex<NlRouter>>) -> u32 {
-        let s = socket.lock().unwrap();
+    async fn get_default_ifindex(s: &NlRouter) -> u32 {
         let family_id = s.resolve_genl_family("nl80211").await.unwrap();
         let mut recv = s
             .send::<_, _, u16, Genlmsghdr<Nl80211Command, Nl80211Attribute>>(
@@ -173,18 +258,20 @@ impl NetworkState {
         }
         return 0
     }
-    pub async fn set_wifi_quality(&mut self, ifindex: u32) {
+
+    /// The signal strength of the station interface in dBm, `None` when it reports none.
+    async fn signal(&self) -> Option<i8> {
         let attrs = vec![
             NlattrBuilder::<Nl80211Attribute, _>::default()
                 .nla_type((u16::from(Nl80211Attribute::Ifindex)).into())
-                .nla_payload(ifindex)
+                .nla_payload(self.ifindex)
                 .build()
                 .unwrap(),
         ]
         .into_iter()
         .collect::<GenlBuffer<Nl80211Attribute, neli::types::Buffer>>();
 
-        let s = self.socket.lock().unwrap();
+        let s = &self.socket;
         let family_id = s.resolve_genl_family("nl80211").await.unwrap();
         let mut recv = s
             .send::<_, _, u16, Genlmsghdr<Nl80211Command, Nl80211Attribute>>(
@@ -201,49 +288,96 @@ impl NetworkState {
             )
             .await.unwrap();
         let msg: Nlmsghdr<u16, Genlmsghdr<Nl80211Command, Nl80211Attribute>> =
-            recv.next().await.unwrap().unwrap();
+            recv.next().await?.ok()?;
         let payload = match msg.nl_payload() {
             NlPayload::Payload(p) => p,
-            _ => return,
+            _ => return None,
         };
         let attr_handle = payload.attrs().get_attr_handle();
-        let station_attributes =
-            attr_handle.get_nested_attributes::<Nl80211StaInfo>(Nl80211Attribute::StaInfo);
-        let signal = station_attributes
-            .unwrap()
-            .get_attribute(Nl80211StaInfo::Signal)
-            .unwrap()
+        let station_attributes = attr_handle
+            .get_nested_attributes::<Nl80211StaInfo>(Nl80211Attribute::StaInfo)
+            .ok()?;
+        station_attributes
+            .get_attribute(Nl80211StaInfo::Signal)?
             .get_payload_as::<i8>()
-            .unwrap();
-        self.signal = 2 * ((signal + 100) as usize)
+            .ok()
     }
 }
 
-pub struct NetworkWidget {
+/// Shows the wifi network and its signal.
+///
+/// Format variables: `icon`, `signal` in percent, `signal_dbm` and `ssid`. The signal ones are
+/// empty while disconnected.
+pub struct NetworkWidget<'a> {
     alignment: Alignment,
+    format: &'a Format,
+    icons: &'a Icons,
+    disconnected_style: Style,
 }
 
-impl<'a> NetworkWidget {
+impl<'a> NetworkWidget<'a> {
+    pub const FORMAT: &'static str = "{icon} [{signal}% ]{ssid} ";
+    pub const VARIABLES: &'static [&'static str] = &["icon", "signal", "signal_dbm", "ssid"];
+
     pub fn new() -> Self {
         Self {
             alignment: Alignment::Left,
+            format: &Formats::shared().network,
+            icons: Icons::shared(),
+            disconnected_style: Theme::default().disconnected,
         }
     }
 
+    pub fn theme(&mut self, theme: &Theme) {
+        self.disconnected_style = theme.disconnected;
+    }
+
+    pub fn format(&mut self, format: &'a Format) {
+        self.format = format;
+    }
+
+    pub fn icons(&mut self, icons: &'a Icons) {
+        self.icons = icons;
+    }
+
     pub fn right_aligned(&mut self) {
         self.alignment = Alignment::Right;
     }
+
+    /// Width needed to show the network.
+    pub fn width(&self, state: &NetworkState) -> u16 {
+        self.line(state).width() as u16
+    }
+
+    fn line(&self, state: &NetworkState) -> Line<'static> {
+        let icon = match state.state {
+            Connection::Connected => {
+                Icons::level(&self.icons.wifi, state.signal().unwrap_or(0), 25)
+            }
+            Connection::Disconnected => self.icons.wifi_disconnected.as_str(),
+        };
+        let mut values = Values::new()
+            .set("icon", icon)
+            .set("ssid", state.ssid.as_str());
+        if let (Some(signal), Some(signal_dbm)) = (state.signal(), state.signal_dbm) {
+            values = values
+                .set("signal", signal.to_string())
+                .set("signal_dbm", signal_dbm.to_string());
+        }
+        self.format.line(&values)
+    }
 }
 
-impl StatefulWidget for NetworkWidget {
+impl StatefulWidget for NetworkWidget<'_> {
     type State = NetworkState;
 
     fn render(self, area: Rect, buf: &mut Buffer, state: &mut NetworkState) {
-        let icon = match state.state {
-            Connection::Connected => ["󰤯", "󰤟", "󰤢", "󰤥", "󰤨"][((state.signal) / 25).clamp(0, 4)],
-            Connection::Disconnected => "󰤮",
+        let style = match state.state {
+            Connection::Connected => Style::new(),
+            Connection::Disconnected => self.disconnected_style,
         };
-        Paragraph::new(format!("{} {}% {} ", icon, state.signal, state.ssid))
+        Paragraph::new(self.line(state))
+            .style(style)
             .alignment(self.alignment)
             .render(area, buf);
     }
@@ -0,0 +1,109 @@
+//! Popups hanging below the bar, under the widget they show the details of.
+//!
+//! Only one is open at a time. It takes the keyboard while open, clicks inside it go to it and
+//! clicks anywhere else close it, like Esc does.
+
+use ratatui::{
+    layout::{Margin, Position, Rect},
+    widgets::ListState,
+};
+
+/// Detail views drawn below the bar.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Popup {
+    Mixer,
+    Sinks,
+    Channels,
+    Calendar,
+}
+
+/// The open popup and where it hangs.
+#[derive(Debug, Clone)]
+pub struct Overlay {
+    pub popup: Popup,
+    /// Area of the widget that opened the popup.
+    pub anchor: Rect,
+    /// Selected row of list popups.
+    pub list: ListState,
+    /// Where the popup was last drawn, empty when it did not fit.
+    pub area: Rect,
+    /// Lines the terminal needs to show all of the popup, from the top of the bar.
+    pub rows: u16,
+}
+
+impl Overlay {
+    pub fn new(popup: Popup, anchor: Rect) -> Self {
+        Self {
+            popup,
+            anchor,
+            list: ListState::default().with_selected(Some(0)),
+            area: Rect::default(),
+            rows: 0,
+        }
+    }
+
+    /// Places a `width` by `height` popup right under the anchor, cut to what fits on `screen`.
+    ///
+    /// Popups of widgets on the left half of the screen start at the widget's left edge, those on
+    /// the right half end at its right edge, so they open towards the middle.
+    pub fn place(&mut self, width: u16, height: u16, screen: Rect) -> Rect {
+        let width = width.min(screen.width);
+        let x = match self.anchor.x + self.anchor.width / 2 < screen.width / 2 {
+            true => self.anchor.x.min(screen.right().saturating_sub(width)),
+            false => self.anchor.right().saturating_sub(width),
+        };
+        let y = self.anchor.bottom();
+        self.rows = y + height;
+        self.area = Rect::new(x, y, width, height).intersection(screen);
+        self.area
+    }
+
+    pub fn contains(&self, position: Position) -> bool {
+        self.area.contains(position)
+    }
+
+    /// The row at `position` of a popup drawn as a bordered list of `len` rows, `None` on its
+    /// border or below its last row.
+    pub fn row(&self, position: Position, len: usize) -> Option<usize> {
+        let inside = self.area.inner(Margin::new(1, 1));
+        if !inside.contains(position) {
+            return None;
+        }
+        let row = self.list.offset() + (position.y - inside.y) as usize;
+        (row < len).then_some(row)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCREEN: Rect = Rect::new(0, 0, 120, 10);
+
+    #[test]
+    fn opens_towards_the_middle() {
+        let mut left = Overlay::new(Popup::Calendar, Rect::new(2, 0, 17, 1));
+        assert_eq!(left.place(26, 9, SCREEN), Rect::new(2, 1, 26, 9));
+        let mut right = Overlay::new(Popup::Mixer, Rect::new(90, 0, 16, 1));
+        assert_eq!(right.place(48, 6, SCREEN), Rect::new(58, 1, 48, 6));
+    }
+
+    #[test]
+    fn cuts_what_does_not_fit() {
+        let mut overlay = Overlay::new(Popup::Calendar, Rect::new(0, 0, 17, 1));
+        assert_eq!(overlay.place(26, 9, Rect::new(0, 0, 20, 4)), Rect::new(0, 1, 20, 3));
+        assert_eq!(overlay.rows, 10);
+        assert_eq!(overlay.place(26, 9, Rect::new(0, 0, 20, 1)).height, 0);
+    }
+
+    #[test]
+    fn clicks_only_land_on_rows() {
+        let mut overlay = Overlay::new(Popup::Mixer, Rect::new(90, 0, 16, 1));
+        overlay.place(48, 5, SCREEN);
+        assert_eq!(overlay.row(Position::new(70, 1), 3), None);
+        assert_eq!(overlay.row(Position::new(70, 2), 3), Some(0));
+        assert_eq!(overlay.row(Position::new(70, 4), 3), Some(2));
+        assert_eq!(overlay.row(Position::new(70, 5), 3), None);
+        assert_eq!(overlay.row(Position::new(70, 4), 2), None);
+    }
+}
@@ -1,26 +1,76 @@
+use pipewire::device::{Device, DeviceListener};
 use pipewire::metadata::{Metadata, MetadataListener};
-use pipewire::node::{Node, NodeListener};
+use pipewire::node::{Node, NodeListener, NodeState};
 use pipewire::spa::param::ParamType;
 use pipewire::types::ObjectType;
-use pipewire::{context::Context, main_loop::MainLoop};
+use pipewire::{channel, context::Context, core::PW_ID_CORE, main_loop::MainLoop};
 
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
+use std::io::Cursor;
 use std::rc::Rc;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
 
-use libspa::pod::{Object, Pod, Value, ValueArray, deserialize::PodDeserializer};
+use libspa::pod::{
+    Object, Pod, Property, PropertyFlags, Value, ValueArray, deserialize::PodDeserializer,
+    serialize::PodSerializer,
+};
+use libspa::utils::{SpaTypes, dict::DictRef};
 
-use tokio::sync::mpsc;
+use serde::{Deserialize, Serialize};
 
-use crate::event::Event;
+use crate::event::{Event, EventSender};
+use crate::pipewirewidget::{AudioSink, CaptureKind, CaptureStream, SinkKind};
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum PipeWireEvent {
     UpdateVolumes(u32, Vec<f32>),
+    /// The SPA audio channel positions of a node, in the same order as its volumes.
+    UpdateChannelMap(u32, Vec<u32>),
     UpdateMuted(u32, bool),
-    SetDefaultSinkName(String),
-    UpdateNodeId(u32, String),
+    /// A `default.*` metadata key changed to the node name it now points at, `None` if unset.
+    UpdateDefault(String, Option<String>),
+    ClearDefaults,
+    /// An application playing audio: node id, application name, media name and whether it is running.
+    UpdateStream(u32, String, String, bool),
+    UpdateSink(AudioSink),
+    /// An application recording the microphone or capturing video.
+    UpdateCapture(CaptureStream),
+    RemoveNode(u32),
+    /// The Bluetooth address of a device, by device id, which BlueZ reports its battery under.
+    UpdateDeviceAddress(u32, Option<String>),
+    /// PipeWire answered its first sync, every existing object has been announced.
+    Connected,
+    /// The connection to PipeWire was lost, everything known about it is stale.
+    Disconnected,
+}
+
+/// Requests sent from the bar to the PipeWire main loop.
+#[derive(Clone, Debug)]
+pub enum PipeWireCommand {
+    /// Sets a node's linear channel volumes.
+    SetVolumes(u32, Vec<f32>),
+    SetMuted(u32, bool),
+    /// Makes the sink with this node name the configured default.
+    SetDefaultSink(String),
+    /// Stops the main loop and ends the PipeWire thread.
+    Quit,
 }
 
+const MIN_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+/// How often a backoff wakes up to see whether the bar is quitting.
+const QUIT_POLL: Duration = Duration::from_millis(50);
+
+/// Device property the bluez5 plugin sets to the Bluetooth address of the device.
+const BLUEZ5_ADDRESS_KEY: &str = "api.bluez5.address";
+
+const SPA_PROP_MUTE: u32 = 65540;
+const SPA_PROP_CHANNEL_VOLUMES: u32 = 65544;
+const SPA_PROP_CHANNEL_MAP: u32 = 65547;
+
 pub fn deserialize(param: Option<&Pod>) -> Option<Object> {
     param
         .and_then(|pod| PodDeserializer::deserialize_any_from(pod.as_bytes()).ok())
@@ -30,119 +80,487 @@ pub fn deserialize(param: Option<&Pod>) -> Option<Object> {
         })
 }
 
-pub fn pw_monitor(sender: mpsc::UnboundedSender<Event>) {
-    tokio::spawn(async move {
-        let mainloop = MainLoop::new(None).unwrap();
-        let context = Context::new(&mainloop).unwrap();
-        let core = context.connect(None).unwrap();
-
-        let registry = Rc::new(core.get_registry().unwrap());
-        let registry_weak = Rc::downgrade(&registry);
-
-        let nodes = Rc::new(RefCell::new(Vec::<(Node, NodeListener)>::new()));
-        let metadatas = Rc::new(RefCell::new(Vec::<(Metadata, MetadataListener)>::new()));
-
-        let _listener = registry
-            .add_listener_local()
-            .global(move |global| {
-                if let Some(registry) = registry_weak.upgrade() {
-                    match global.type_ {
-                        ObjectType::Node => {
-                            let obj_id = global.id;
-                            let node: Node = registry.bind(global).unwrap();
-                            let _sender = sender.clone();
-                            let __sender = sender.clone();
-                            let listener = node
-                                .add_listener_local()
-                                .info(move |info| {
-                                    if let Some(name) = info.props().unwrap().get("node.name") {
-                                        __sender
-                                            .send(Event::UpdatePipeWireState(
-                                                PipeWireEvent::UpdateNodeId(
-                                                    info.id(),
-                                                    name.to_string(),
+/// Serializes a `Props` param holding a single property, for `Node::set_param`.
+fn props_param(key: u32, value: Value) -> Vec<u8> {
+    PodSerializer::serialize(
+        Cursor::new(Vec::new()),
+        &Value::Object(Object {
+            type_: SpaTypes::ObjectParamProps.as_raw(),
+            id: ParamType::Props.as_raw(),
+            properties: vec![Property {
+                key,
+                flags: PropertyFlags::empty(),
+                value,
+            }],
+        }),
+    )
+    .unwrap()
+    .0
+    .into_inner()
+}
+
+/// Reads the node name out of a metadata value like `{"name":"alsa_output.pci-0000_00_1f.3"}`.
+pub fn parse_metadata_name(value: &str) -> Option<String> {
+    let value: serde_json::Value = serde_json::from_str(value).ok()?;
+    Some(value.get("name")?.as_str()?.to_string())
+}
+
+/// Guesses what kind of output a sink is from its node properties.
+fn sink_kind(props: &DictRef) -> SinkKind {
+    let name = props.get("node.name").unwrap_or("").to_lowercase();
+    let description = props.get("node.description").unwrap_or("").to_lowercase();
+    match props.get("device.form-factor") {
+        Some("headphone" | "headset" | "handset" | "hands-free") => SinkKind::Headphones,
+        Some("tv") => SinkKind::Hdmi,
+        Some(_) => SinkKind::Speakers,
+        None if name.contains("hdmi") || description.contains("hdmi") => SinkKind::Hdmi,
+        None if name.starts_with("bluez") || description.contains("headphone") => {
+            SinkKind::Headphones
+        }
+        None => SinkKind::Speakers,
+    }
+}
+
+/// Runs the PipeWire main loop on its own thread, forwarding changes to `sender` and applying
+/// `commands`.
+///
+/// The loop is restarted with exponential backoff whenever the connection to PipeWire is lost,
+/// sending `PipeWireEvent::Disconnected` so stale state can be dropped. The thread exits after
+/// `PipeWireCommand::Quit`, once `quit` is set or once the bar stops listening. Only a connected
+/// session reads commands, so `quit` is what stops the thread while PipeWire is down.
+pub fn pw_monitor(
+    sender: EventSender,
+    commands: channel::Receiver<PipeWireCommand>,
+    quit: Arc<AtomicBool>,
+) -> thread::JoinHandle<()> {
+    thread::Builder::new()
+        .name("pipewire".to_string())
+        .spawn(move || {
+            let stopped = || quit.load(Ordering::Relaxed) || sender.is_closed();
+            reconnect(&sender, commands, stopped, run_session);
+        })
+        .expect("failed to spawn the PipeWire thread")
+}
+
+/// Runs sessions until one quits or `stopped` is true, backing off while PipeWire is down.
+pub fn reconnect(
+    sender: &EventSender,
+    mut commands: channel::Receiver<PipeWireCommand>,
+    stopped: impl Fn() -> bool,
+    mut session: impl FnMut(&EventSender, channel::Receiver<PipeWireCommand>) -> Session,
+) {
+    let mut backoff = MIN_BACKOFF;
+    while !stopped() {
+        match session(sender, commands) {
+            Session::Quit => return,
+            Session::Lost {
+                commands: receiver,
+                connected,
+            } => {
+                commands = receiver;
+                if connected {
+                    backoff = MIN_BACKOFF;
+                    let disconnected = Event::UpdatePipeWireState(PipeWireEvent::Disconnected);
+                    if sender.blocking_send(disconnected).is_err() {
+                        return;
+                    }
+                }
+            }
+        }
+        // Sleep in slices, so quitting does not wait out a long backoff
+        let until = Instant::now() + backoff;
+        while !stopped() && Instant::now() < until {
+            thread::sleep(QUIT_POLL.min(until.saturating_duration_since(Instant::now())));
+        }
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+}
+
+/// How a connection to PipeWire ended.
+pub enum Session {
+    /// `PipeWireCommand::Quit` was received.
+    Quit,
+    /// PipeWire went away or could not be reached, `connected` is whether it ever answered.
+    Lost {
+        commands: channel::Receiver<PipeWireCommand>,
+        connected: bool,
+    },
+}
+
+/// Connects to PipeWire and runs its main loop until the connection is lost or a quit is
+/// requested.
+fn run_session(
+    sender: &EventSender,
+    commands: channel::Receiver<PipeWireCommand>,
+) -> Session {
+    let lost = |commands| Session::Lost {
+        commands,
+        connected: false,
+    };
+    let Ok(mainloop) = MainLoop::new(None) else {
+        return lost(commands);
+    };
+    let Ok(context) = Context::new(&mainloop) else {
+        return lost(commands);
+    };
+    let Ok(core) = context.connect(None) else {
+        return lost(commands);
+    };
+    let Ok(registry) = core.get_registry() else {
+        return lost(commands);
+    };
+    let registry = Rc::new(registry);
+    let registry_weak = Rc::downgrade(&registry);
+
+    let quit = Rc::new(Cell::new(false));
+    let connected = Rc::new(Cell::new(false));
+
+    let nodes = Rc::new(RefCell::new(Vec::<(u32, Node, NodeListener)>::new()));
+    let devices = Rc::new(RefCell::new(Vec::<(u32, Device, DeviceListener)>::new()));
+    let metadatas = Rc::new(RefCell::new(
+        Vec::<(u32, String, Metadata, MetadataListener)>::new(),
+    ));
+
+    let command_nodes = nodes.clone();
+    let command_metadatas = metadatas.clone();
+    let command_mainloop = mainloop.downgrade();
+    let command_quit = quit.clone();
+    let commands = commands.attach(mainloop.loop_(), move |command| {
+        let (id, param) = match command {
+            PipeWireCommand::Quit => {
+                command_quit.set(true);
+                if let Some(mainloop) = command_mainloop.upgrade() {
+                    mainloop.quit();
+                }
+                return;
+            }
+            PipeWireCommand::SetVolumes(id, volumes) => (
+                id,
+                props_param(
+                    SPA_PROP_CHANNEL_VOLUMES,
+                    Value::ValueArray(ValueArray::Float(volumes)),
+                ),
+            ),
+            PipeWireCommand::SetMuted(id, muted) => {
+                (id, props_param(SPA_PROP_MUTE, Value::Bool(muted)))
+            }
+            PipeWireCommand::SetDefaultSink(name) => {
+                if let Some((_, _, metadata, _)) = command_metadatas
+                    .borrow()
+                    .iter()
+                    .find(|(_, metadata_name, _, _)| metadata_name == "default")
+                {
+                    metadata.set_property(
+                        0,
+                        "default.configured.audio.sink",
+                        Some("Spa:String:JSON"),
+                        Some(&serde_json::json!({ "name": name }).to_string()),
+                    );
+                }
+                return;
+            }
+        };
+        if let Some((_, node, _)) = command_nodes
+            .borrow()
+            .iter()
+            .find(|(node_id, _, _)| *node_id == id)
+        {
+            node.set_param(ParamType::Props, 0, Pod::from_bytes(&param).unwrap());
+        }
+    });
+
+    // The reply to this sync means the initial burst of globals has been delivered
+    let pending = core.sync(0).ok();
+    let done_sender = sender.clone();
+    let done_connected = connected.clone();
+    let error_mainloop = mainloop.downgrade();
+    let _core_listener = core
+        .add_listener_local()
+        .done(move |id, seq| {
+            if id == PW_ID_CORE && Some(seq) == pending && !done_connected.get() {
+                done_connected.set(true);
+                let _ = done_sender
+                    .blocking_send(Event::UpdatePipeWireState(PipeWireEvent::Connected));
+            }
+        })
+        .error(move |id, _seq, _res, _message| {
+            // Errors on the core object itself mean the connection is gone
+            if id != PW_ID_CORE {
+                return;
+            }
+            if let Some(mainloop) = error_mainloop.upgrade() {
+                mainloop.quit();
+            }
+        })
+        .register();
+
+    let remove_sender = sender.clone();
+    let remove_nodes = nodes.clone();
+    let remove_metadatas = metadatas.clone();
+    let remove_devices = devices.clone();
+    let sender = sender.clone();
+    let _listener = registry
+        .add_listener_local()
+        .global(move |global| {
+            if let Some(registry) = registry_weak.upgrade() {
+                match global.type_ {
+                    ObjectType::Node => {
+                        let obj_id = global.id;
+                        let node: Node = registry.bind(global).unwrap();
+                        let _sender = sender.clone();
+                        let __sender = sender.clone();
+                        let listener = node
+                            .add_listener_local()
+                            .info(move |info| {
+                                let Some(props) = info.props() else {
+                                    return;
+                                };
+                                if props.get("media.class") == Some("Stream/Output/Audio") {
+                                    let _ = __sender
+                                        .blocking_send(Event::UpdatePipeWireState(
+                                            PipeWireEvent::UpdateStream(
+                                                info.id(),
+                                                props
+                                                    .get("application.name")
+                                                    .unwrap_or("Unknown")
+                                                    .to_string(),
+                                                props.get("media.name").unwrap_or("").to_string(),
+                                                matches!(info.state(), NodeState::Running),
+                                            ),
+                                        ));
+                                }
+                                let capture_kind = match props.get("media.class") {
+                                    Some("Stream/Input/Audio") => Some(CaptureKind::Microphone),
+                                    Some("Stream/Input/Video") => Some(CaptureKind::Video),
+                                    _ => None,
+                                };
+                                // Level meters like pavucontrol's read sink monitors, not the mic
+                                let monitor = props.get("stream.monitor") == Some("true");
+                                if let Some(kind) = capture_kind.filter(|_| !monitor) {
+                                    let _ = __sender
+                                        .blocking_send(Event::UpdatePipeWireState(
+                                            PipeWireEvent::UpdateCapture(CaptureStream {
+                                                id: info.id(),
+                                                application: props
+                                                    .get("application.name")
+                                                    .or(props.get("application.process.binary"))
+                                                    .or(props.get("node.name"))
+                                                    .unwrap_or("Unknown")
+                                                    .to_string(),
+                                                kind,
+                                                running: matches!(
+                                                    info.state(),
+                                                    NodeState::Running
                                                 ),
-                                            ))
-                                            .unwrap();
-                                    }
-                                })
-                                .param(move |_seq, id, _index, _next, param| {
-                                    if let Some(param) = deserialize(param) {
-                                        if id == ParamType::Props {
-                                            for property in param.clone().properties {
-                                                match property.key {
-                                                    65540 => { // Mute event
-                                                        if let Value::Bool(mute_bool) =
-                                                            property.value
-                                                        {
-                                                            _sender
-                                                                .send(Event::UpdatePipeWireState(
-                                                                    PipeWireEvent::UpdateMuted(
-                                                                        obj_id, mute_bool,
-                                                                    ),
-                                                                ))
-                                                                .unwrap();
-                                                        }
-                                                    }
-
-                                                    65544 => { // Volume change event
-                                                        if let Value::ValueArray(
-                                                            ValueArray::Float(floats),
-                                                        ) = property.value
-                                                        {
-                                                            _sender
-                                                                .send(Event::UpdatePipeWireState(
-                                                                    PipeWireEvent::UpdateVolumes(
-                                                                        obj_id, floats,
-                                                                    ),
-                                                                ))
-                                                                .unwrap();
-                                                        }
-                                                    }
-                                                    _ => (),
+                                            }),
+                                        ));
+                                }
+                                if props.get("media.class") == Some("Audio/Sink") {
+                                    let name = props.get("node.name").unwrap_or("");
+                                    let description =
+                                        props.get("node.description").unwrap_or(name);
+                                    let _ = __sender
+                                        .blocking_send(Event::UpdatePipeWireState(
+                                            PipeWireEvent::UpdateSink(AudioSink {
+                                                id: info.id(),
+                                                name: name.to_string(),
+                                                description: description.to_string(),
+                                                nick: props
+                                                    .get("node.nick")
+                                                    .unwrap_or(description)
+                                                    .to_string(),
+                                                kind: sink_kind(props),
+                                                device: props
+                                                    .get("device.id")
+                                                    .and_then(|id| id.parse().ok()),
+                                                codec: props
+                                                    .get("api.bluez5.codec")
+                                                    .map(str::to_string),
+                                                profile: props
+                                                    .get("api.bluez5.profile")
+                                                    .map(str::to_string),
+                                                ..Default::default()
+                                            }),
+                                        ));
+                                }
+                            })
+                            .param(move |_seq, id, _index, _next, param| {
+                                if id != ParamType::Props {
+                                    return;
+                                }
+                                if let Some(param) = deserialize(param) {
+                                    for property in param.clone().properties {
+                                        match property.key {
+                                            SPA_PROP_MUTE => {
+                                                if let Value::Bool(mute_bool) =
+                                                    property.value
+                                                {
+                                                    let _ = _sender
+                                                        .blocking_send(Event::UpdatePipeWireState(
+                                                            PipeWireEvent::UpdateMuted(
+                                                                obj_id, mute_bool,
+                                                            ),
+                                                        ));
+                                                }
+                                            }
+
+                                            SPA_PROP_CHANNEL_VOLUMES => {
+                                                if let Value::ValueArray(
+                                                    ValueArray::Float(floats),
+                                                ) = property.value
+                                                {
+                                                    let _ = _sender
+                                                        .blocking_send(Event::UpdatePipeWireState(
+                                                            PipeWireEvent::UpdateVolumes(
+                                                                obj_id, floats,
+                                                            ),
+                                                        ));
+                                                }
+                                            }
+                                            SPA_PROP_CHANNEL_MAP => {
+                                                if let Value::ValueArray(ValueArray::Id(
+                                                    positions,
+                                                )) = property.value
+                                                {
+                                                    let _ = _sender
+                                                        .blocking_send(Event::UpdatePipeWireState(
+                                                            PipeWireEvent::UpdateChannelMap(
+                                                                obj_id,
+                                                                positions
+                                                                    .iter()
+                                                                    .map(|id| id.0)
+                                                                    .collect(),
+                                                            ),
+                                                        ));
                                                 }
                                             }
-                                        }
-                                        match id {
-                                            ParamType::Props => {}
                                             _ => (),
                                         }
                                     }
-                                })
-                                .register();
-                            node.subscribe_params(&[ParamType::Props]);
-                            nodes.borrow_mut().push((node, listener));
-                        }
-                        ObjectType::Metadata => {
-                            let metadata: Metadata = registry.bind(global).unwrap();
-                            let _sender = sender.clone();
-                            let listener = metadata
-                                .add_listener_local()
-                                .property(move |_subject, key, _type, value| {
-                                    if let Some(key) = key {
-                                        if key == "default.audio.sink" {
-                                            let name = value.unwrap_or("{\"name\":\"\"}");
-                                            _sender
-                                                .send(Event::UpdatePipeWireState(
-                                                    PipeWireEvent::SetDefaultSinkName(
-                                                        name.get(9..(name.len() - 2))
-                                                            .unwrap()
-                                                            .to_string(),
-                                                    ),
-                                                ))
-                                                .unwrap();
-                                        }
+                                }
+                            })
+                            .register();
+                        node.subscribe_params(&[ParamType::Props]);
+                        nodes.borrow_mut().push((obj_id, node, listener));
+                    }
+                    // Only Bluetooth devices are watched, for the address their battery is under
+                    ObjectType::Device
+                        if global.props.and_then(|props| props.get("device.api"))
+                            == Some("bluez5") =>
+                    {
+                        let device: Device = registry.bind(global).unwrap();
+                        let _sender = sender.clone();
+                        let listener = device
+                            .add_listener_local()
+                            .info(move |info| {
+                                let address = info
+                                    .props()
+                                    .and_then(|props| props.get(BLUEZ5_ADDRESS_KEY))
+                                    .map(|address| address.to_string());
+                                let _ = _sender
+                                    .blocking_send(Event::UpdatePipeWireState(
+                                        PipeWireEvent::UpdateDeviceAddress(info.id(), address),
+                                    ));
+                            })
+                            .register();
+                        devices.borrow_mut().push((global.id, device, listener));
+                    }
+                    ObjectType::Metadata => {
+                        let metadata_name = global
+                            .props
+                            .and_then(|props| props.get("metadata.name"))
+                            .unwrap_or("")
+                            .to_string();
+                        let metadata: Metadata = registry.bind(global).unwrap();
+                        let _sender = sender.clone();
+                        let is_default = metadata_name == "default";
+                        let listener = metadata
+                            .add_listener_local()
+                            .property(move |_subject, key, _type, value| {
+                                if !is_default {
+                                    return 0;
+                                }
+                                let event = match key {
+                                    Some(key) if key.starts_with("default.") => {
+                                        PipeWireEvent::UpdateDefault(
+                                            key.to_string(),
+                                            value.and_then(parse_metadata_name),
+                                        )
                                     }
-                                    0
-                                })
-                                .register();
-                            metadatas.borrow_mut().push((metadata, listener));
-                        }
-                        _ => (),
+                                    Some(_) => return 0,
+                                    None => PipeWireEvent::ClearDefaults,
+                                };
+                                let _ = _sender.blocking_send(Event::UpdatePipeWireState(event));
+                                0
+                            })
+                            .register();
+                        metadatas
+                            .borrow_mut()
+                            .push((global.id, metadata_name, metadata, listener));
                     }
+                    _ => (),
                 }
-            })
-            .register();
-        mainloop.run();
-    });
+            }
+        })
+        .global_remove(move |id| {
+            // Dropping the proxies and their listeners frees them on our side too
+            let mut tracked = false;
+            remove_nodes.borrow_mut().retain(|(node_id, _, _)| {
+                tracked |= *node_id == id;
+                *node_id != id
+            });
+            remove_metadatas.borrow_mut().retain(|(metadata_id, _, _, _)| {
+                tracked |= *metadata_id == id;
+                *metadata_id != id
+            });
+            remove_devices.borrow_mut().retain(|(device_id, _, _)| {
+                tracked |= *device_id == id;
+                *device_id != id
+            });
+            // Ports, links and clients come and go too, the app never heard of them
+            if tracked {
+                let _ = remove_sender
+                    .blocking_send(Event::UpdatePipeWireState(PipeWireEvent::RemoveNode(id)));
+            }
+        })
+        .register();
+    mainloop.run();
+
+    let commands = commands.deattach();
+    match quit.get() {
+        true => Session::Quit,
+        false => Session::Lost {
+            commands,
+            connected: connected.get(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_metadata_name() {
+        assert_eq!(
+            parse_metadata_name(r#"{"name":"alsa_output.pci-0000_00_1f.3.analog-stereo"}"#),
+            Some("alsa_output.pci-0000_00_1f.3.analog-stereo".to_string())
+        );
+    }
+
+    #[test]
+    fn parses_escaped_metadata_name() {
+        assert_eq!(
+            parse_metadata_name(r#"{ "name": "my \"sink\" \u00e9" }"#),
+            Some("my \"sink\" é".to_string())
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_metadata() {
+        assert_eq!(parse_metadata_name(""), None);
+        assert_eq!(parse_metadata_name("{}"), None);
+        assert_eq!(parse_metadata_name(r#"{"name":3}"#), None);
+        assert_eq!(parse_metadata_name("alsa_output"), None);
+    }
 }
@@ -1,57 +1,450 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use unicode_width::UnicodeWidthStr;
+
 use ratatui::{
     buffer::Buffer,
     layout::{Alignment, Rect},
-    style::Color,
-    widgets::{Paragraph, StatefulWidget, Tabs, Widget},
+    style::Style,
+    text::{Line, Span},
+    widgets::{Block, Clear, List, ListState, Paragraph, StatefulWidget, Tabs, Widget},
 };
 
-#[derive(Debug, Clone)]
+use crate::format::{Format, Formats, Values};
+use crate::icons::Icons;
+use crate::pipemon::PipeWireEvent;
+use crate::theme::Theme;
+
+/// An application stream playing audio.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AudioStream {
+    pub id: u32,
+    pub application: String,
+    pub media: String,
+    pub volume: u8,
+    /// Linear channel volumes, as PipeWire reports them, empty until it first does.
+    pub channel_volumes: Vec<f32>,
+    pub muted: bool,
+    pub running: bool,
+}
+
+impl AudioStream {
+    /// Linear channel volumes that move the shown volume by `delta` percent, scaling every channel
+    /// by the same factor so their balance is kept. Stepping never goes past 100%, or past the
+    /// current volume when something else already raised it higher.
+    pub fn volumes_changed_by(&self, delta: i16) -> Vec<f32> {
+        let max = (self.volume as i16).max(100);
+        let volume = (self.volume as i16 + delta).clamp(0, max) as f32 / 100.0;
+        // PipeWire volumes are cubic, the bar shows them as their cube root
+        if self.volume == 0 {
+            return vec![volume.powi(3); self.channel_volumes.len()];
+        }
+        let factor = (volume / (self.volume as f32 / 100.0)).powi(3);
+        self.channel_volumes
+            .iter()
+            .map(|channel| channel * factor)
+            .collect()
+    }
+}
+
+/// What a capture stream is recording.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum CaptureKind {
+    Microphone,
+    /// Screen sharing or a camera, PipeWire does not tell them apart.
+    Video,
+}
+
+/// An application recording audio or video.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CaptureStream {
+    pub id: u32,
+    pub application: String,
+    pub kind: CaptureKind,
+    pub running: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum SinkKind {
+    Headphones,
+    #[default]
+    Speakers,
+    Hdmi,
+}
+
+/// An output device audio can be played on.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AudioSink {
+    pub id: u32,
+    /// The node name, which is what the default sink metadata refers to.
+    pub name: String,
+    pub description: String,
+    pub nick: String,
+    pub kind: SinkKind,
+    /// Kept for every sink so switching the default shows the right volume straight away.
+    pub channel_volumes: Vec<u8>,
+    pub channel_map: Vec<u32>,
+    pub muted: bool,
+    /// The device object this sink belongs to, whose Bluetooth address its battery is under.
+    pub device: Option<u32>,
+    /// Bluetooth codec, like `sbc` or `ldac`.
+    pub codec: Option<String>,
+    /// Bluetooth profile, like `a2dp-sink` or `headset-head-unit`.
+    pub profile: Option<String>,
+}
+
+impl AudioSink {
+    /// Whether a Bluetooth sink is on the low quality HSP/HFP headset profile rather than A2DP.
+    pub fn headset_profile(&self) -> bool {
+        self.profile.as_deref().is_some_and(|profile| {
+            profile.starts_with("headset") || profile.contains("hfp") || profile.contains("hsp")
+        })
+    }
+}
+
+/// The node names the `default` metadata object points at.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DefaultNodes {
+    pub audio_sink: Option<String>,
+    pub audio_source: Option<String>,
+    pub video_source: Option<String>,
+    pub configured_audio_sink: Option<String>,
+    pub configured_audio_source: Option<String>,
+    pub configured_video_source: Option<String>,
+}
+
+impl DefaultNodes {
+    /// Applies a `default.*` metadata key, ignoring ones it does not know.
+    pub fn update(&mut self, key: &str, name: Option<String>) {
+        let field = match key {
+            "default.audio.sink" => &mut self.audio_sink,
+            "default.audio.source" => &mut self.audio_source,
+            "default.video.source" => &mut self.video_source,
+            "default.configured.audio.sink" => &mut self.configured_audio_sink,
+            "default.configured.audio.source" => &mut self.configured_audio_source,
+            "default.configured.video.source" => &mut self.configured_video_source,
+            _ => return,
+        };
+        *field = name;
+    }
+}
+
+/// Channels whose volumes differ by more than this are shown as unbalanced.
+const BALANCE_TOLERANCE: u8 = 2;
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct PipewireState {
+    /// Average of the default sink's channel volumes.
     pub volume: u8,
+    pub channel_volumes: Vec<u8>,
+    /// SPA audio channel positions of the default sink, in the same order as `channel_volumes`.
+    pub channel_map: Vec<u32>,
     pub muted: bool,
-    pub default_sink_name: String,
-    default_sink_id: u32,
+    pub defaults: DefaultNodes,
+    default_sink_id: Option<u32>,
+    pub streams: Vec<AudioStream>,
+    pub sinks: Vec<AudioSink>,
+    pub captures: Vec<CaptureStream>,
+    /// Bluetooth address of devices, by device id.
+    pub device_addresses: HashMap<u32, String>,
+    /// Battery percentage of Bluetooth devices, by address. These come from BlueZ rather than
+    /// PipeWire, so outlive a PipeWire restart.
+    pub bluetooth_batteries: HashMap<String, u8>,
+    /// Whether PipeWire is reachable, the rest of the state is empty while it is not.
+    pub connected: bool,
 }
 
 impl PipewireState {
     pub fn new() -> Self {
         Self {
             volume: 100,
+            channel_volumes: Vec::new(),
+            channel_map: Vec::new(),
             muted: false,
-            default_sink_name: "".to_string(),
-            default_sink_id: 0,
+            defaults: DefaultNodes::default(),
+            default_sink_id: None,
+            streams: Vec::new(),
+            sinks: Vec::new(),
+            captures: Vec::new(),
+            device_addresses: HashMap::new(),
+            bluetooth_batteries: HashMap::new(),
+            connected: false,
+        }
+    }
+
+    /// Applies an event, returning whether it changed anything.
+    pub fn update(&mut self, event: PipeWireEvent) -> bool {
+        let before = self.clone();
+        match event {
+            PipeWireEvent::UpdateVolumes(id, items) => self.update_volumes(id, items),
+            PipeWireEvent::UpdateChannelMap(id, positions) => {
+                self.update_channel_map(id, positions)
+            }
+            PipeWireEvent::UpdateMuted(id, muted) => self.update_muted(id, muted),
+            PipeWireEvent::UpdateDefault(key, name) => self.update_default(&key, name),
+            PipeWireEvent::ClearDefaults => self.clear_defaults(),
+            PipeWireEvent::UpdateStream(id, application, media, running) => {
+                self.update_stream(id, application, media, running)
+            }
+            PipeWireEvent::UpdateSink(sink) => self.update_sink(sink),
+            PipeWireEvent::UpdateCapture(capture) => self.update_capture(capture),
+            PipeWireEvent::RemoveNode(id) => self.remove_node(id),
+            PipeWireEvent::UpdateDeviceAddress(id, address) => {
+                self.update_device_address(id, address)
+            }
+            PipeWireEvent::Connected => self.connected = true,
+            // Nodes get new ids after a restart, so start over empty
+            PipeWireEvent::Disconnected => {
+                *self = PipewireState {
+                    bluetooth_batteries: std::mem::take(&mut self.bluetooth_batteries),
+                    ..PipewireState::new()
+                }
+            }
+        }
+        *self != before
+    }
+
+    /// Adds a sink or refreshes its description, keeping the volumes already known for it.
+    pub fn update_sink(&mut self, sink: AudioSink) {
+        match self.sinks.iter_mut().find(|known| known.id == sink.id) {
+            Some(known) => {
+                known.name = sink.name;
+                known.description = sink.description;
+                known.nick = sink.nick;
+                known.kind = sink.kind;
+                known.device = sink.device;
+                known.codec = sink.codec;
+                known.profile = sink.profile;
+            }
+            None => self.sinks.push(sink),
+        }
+        self.resolve_default_sink();
+    }
+
+    pub fn default_sink_name(&self) -> &str {
+        self.defaults.audio_sink.as_deref().unwrap_or("")
+    }
+
+    pub fn default_sink(&self) -> Option<&AudioSink> {
+        self.sinks
+            .iter()
+            .find(|sink| sink.name == self.default_sink_name())
+    }
+
+    pub fn update_default(&mut self, key: &str, name: Option<String>) {
+        self.defaults.update(key, name);
+        self.resolve_default_sink();
+    }
+
+    pub fn clear_defaults(&mut self) {
+        self.defaults = DefaultNodes::default();
+        self.resolve_default_sink();
+    }
+
+    /// Points the bar at the sink the metadata names, which may have appeared, changed or gone.
+    fn resolve_default_sink(&mut self) {
+        let sink = self.default_sink().cloned().unwrap_or_default();
+        self.default_sink_id = self.default_sink().map(|sink| sink.id);
+        self.volume = average(&sink.channel_volumes);
+        self.channel_volumes = sink.channel_volumes;
+        self.channel_map = sink.channel_map;
+        self.muted = sink.muted;
+    }
+
+    pub fn update_stream(&mut self, id: u32, application: String, media: String, running: bool) {
+        match self.streams.iter_mut().find(|stream| stream.id == id) {
+            Some(stream) => {
+                stream.application = application;
+                stream.media = media;
+                stream.running = running;
+            }
+            None => self.streams.push(AudioStream {
+                id,
+                application,
+                media,
+                volume: 100,
+                channel_volumes: Vec::new(),
+                muted: false,
+                running,
+            }),
+        }
+    }
+
+    pub fn remove_node(&mut self, id: u32) {
+        self.streams.retain(|stream| stream.id != id);
+        self.sinks.retain(|sink| sink.id != id);
+        self.captures.retain(|capture| capture.id != id);
+        self.device_addresses.remove(&id);
+        if self.default_sink_id == Some(id) {
+            self.resolve_default_sink();
         }
     }
 
-    pub fn set_default_sink_id(&mut self, name: String, id: u32) {
-        if name == self.default_sink_name {
-            self.default_sink_id = id;
+    pub fn update_capture(&mut self, capture: CaptureStream) {
+        match self.captures.iter_mut().find(|known| known.id == capture.id) {
+            Some(known) => *known = capture,
+            None => self.captures.push(capture),
         }
     }
 
+    /// Names of the applications currently recording this kind of input, without duplicates.
+    pub fn capturing(&self, kind: CaptureKind) -> Vec<&str> {
+        let mut applications: Vec<&str> = self
+            .captures
+            .iter()
+            .filter(|capture| capture.running && capture.kind == kind)
+            .map(|capture| capture.application.as_str())
+            .collect();
+        applications.sort_unstable();
+        applications.dedup();
+        applications
+    }
+
+    pub fn update_device_address(&mut self, id: u32, address: Option<String>) {
+        match address {
+            Some(address) => self.device_addresses.insert(id, address),
+            None => self.device_addresses.remove(&id),
+        };
+    }
+
+    /// Takes a new reading of the Bluetooth batteries, returning whether it differs from the last.
+    pub fn update_bluetooth_batteries(&mut self, batteries: HashMap<String, u8>) -> bool {
+        let changed = self.bluetooth_batteries != batteries;
+        self.bluetooth_batteries = batteries;
+        changed
+    }
+
+    /// Battery percentage of the default sink's device, when it reports one.
+    pub fn default_sink_battery(&self) -> Option<u8> {
+        let device = self.default_sink()?.device?;
+        let address = self.device_addresses.get(&device)?;
+        self.bluetooth_batteries.get(address).copied()
+    }
+
+    /// Number of applications currently playing audio.
+    pub fn playing(&self) -> usize {
+        self.streams.iter().filter(|stream| stream.running).count()
+    }
+
     pub fn update_volumes(&mut self, id: u32, floats: Vec<f32>) {
-        if id == self.default_sink_id {
-            self.volume = (floats[0].cbrt() * 100.0) as u8;
+        if floats.is_empty() {
+            return;
+        }
+        let volumes: Vec<u8> = floats
+            .iter()
+            .map(|volume| (volume.cbrt() * 100.0).round() as u8)
+            .collect();
+        let average = average(&volumes);
+        if let Some(sink) = self.sinks.iter_mut().find(|sink| sink.id == id) {
+            sink.channel_volumes = volumes.clone();
+        }
+        if self.default_sink_id == Some(id) {
+            self.volume = average;
+            self.channel_volumes = volumes;
+        }
+        if let Some(stream) = self.streams.iter_mut().find(|stream| stream.id == id) {
+            stream.volume = average;
+            stream.channel_volumes = floats;
+        }
+    }
+
+    pub fn update_channel_map(&mut self, id: u32, positions: Vec<u32>) {
+        if let Some(sink) = self.sinks.iter_mut().find(|sink| sink.id == id) {
+            sink.channel_map = positions.clone();
+        }
+        if self.default_sink_id == Some(id) {
+            self.channel_map = positions;
+        }
+    }
+
+    /// Whether the default sink's channels are at noticeably different volumes.
+    pub fn unbalanced(&self) -> bool {
+        let max = self.channel_volumes.iter().max();
+        let min = self.channel_volumes.iter().min();
+        match (max, min) {
+            (Some(max), Some(min)) => max - min > BALANCE_TOLERANCE,
+            _ => false,
+        }
+    }
+
+    /// Short name of the default sink's nth channel, like `FL` or `LFE`.
+    pub fn channel_name(&self, index: usize) -> String {
+        match self.channel_map.get(index) {
+            Some(position) => channel_position_name(*position).to_string(),
+            None => format!("{}", index + 1),
         }
     }
     pub fn update_muted(&mut self, id: u32, muted: bool) {
-        if id == self.default_sink_id {
+        if let Some(sink) = self.sinks.iter_mut().find(|sink| sink.id == id) {
+            sink.muted = muted;
+        }
+        if self.default_sink_id == Some(id) {
             self.muted = muted;
         }
+        if let Some(stream) = self.streams.iter_mut().find(|stream| stream.id == id) {
+            stream.muted = muted;
+        }
     }
 }
 
-pub struct PipewireWidget {
+/// Shows the volume of the default sink.
+///
+/// Format variables: `icon`, `volume` in percent, `unbalanced` when the channels differ,
+/// `playing` (the number of streams, empty when none) with its `playing_icon`, `sink` (an icon for
+/// the kind of device), `sink_name`, and for Bluetooth sinks `battery` with its `battery_icon` and
+/// `codec`, highlighted when it fell back to HSP/HFP.
+pub struct PipewireWidget<'a> {
     alignment: Alignment,
+    format: &'a Format,
+    icons: &'a Icons,
+    muted_style: Style,
+    disconnected_style: Style,
+    warning_style: Style,
 }
 
-impl<'a> PipewireWidget {
+impl<'a> PipewireWidget<'a> {
+    pub const FORMAT: &'static str =
+        "[{playing_icon}{playing} ]{icon} {volume}{unbalanced}[ {sink}][ {battery_icon}{battery}%][ {codec}]";
+    pub const VARIABLES: &'static [&'static str] = &[
+        "icon",
+        "volume",
+        "unbalanced",
+        "playing",
+        "playing_icon",
+        "sink",
+        "sink_name",
+        "battery",
+        "battery_icon",
+        "codec",
+    ];
+
     pub fn new() -> Self {
+        let theme = Theme::default();
         Self {
             alignment: Alignment::Left,
+            format: &Formats::shared().volume,
+            icons: Icons::shared(),
+            muted_style: theme.muted,
+            disconnected_style: theme.disconnected,
+            warning_style: theme.warning,
         }
     }
 
+    pub fn theme(&mut self, theme: &Theme) {
+        self.muted_style = theme.muted;
+        self.disconnected_style = theme.disconnected;
+        self.warning_style = theme.warning;
+    }
+
+    pub fn format(&mut self, format: &'a Format) {
+        self.format = format;
+    }
+
+    pub fn icons(&mut self, icons: &'a Icons) {
+        self.icons = icons;
+    }
+
     pub fn right_aligned(&mut self) {
         self.alignment = Alignment::Right;
     }
@@ -59,17 +452,504 @@ impl<'a> PipewireWidget {
     pub fn center_aligned(&mut self) {
         self.alignment = Alignment::Center;
     }
+
+    /// Width needed to show the volume.
+    pub fn width(&self, state: &PipewireState) -> u16 {
+        self.line(state).width() as u16
+    }
+
+    fn line(&self, state: &PipewireState) -> Line<'static> {
+        if !state.connected {
+            return Line::raw(format!("{} --", self.icons.audio_disconnected));
+        }
+        let icon = match state.muted {
+            true => self.icons.muted.as_str(),
+            false => Icons::level(&self.icons.volume, state.volume as usize, 34),
+        };
+        let playing = match state.playing() {
+            0 => "".to_string(),
+            playing => playing.to_string(),
+        };
+        let balance = match state.unbalanced() {
+            true => self.icons.unbalanced.as_str(),
+            false => "",
+        };
+        let mut values = Values::new()
+            .set("icon", icon)
+            .set("volume", state.volume.to_string())
+            .set("unbalanced", balance)
+            .set("playing", playing)
+            .set("playing_icon", self.icons.playing.as_str())
+            .set("battery_icon", self.icons.bluetooth_battery.as_str());
+        if let Some(battery) = state.default_sink_battery() {
+            values = values.set("battery", battery.to_string());
+        }
+        if let Some(sink) = state.default_sink() {
+            let codec = sink.codec.as_deref().unwrap_or("").to_uppercase().replace('_', "-");
+            values = values
+                .set("sink", self.icons.sink(sink.kind))
+                .set("sink_name", sink.nick.as_str());
+            values = match sink.headset_profile() {
+                true => values.styled("codec", format!("HFP {}", codec), self.warning_style),
+                false => values.set("codec", codec),
+            };
+        }
+        self.format.line(&values)
+    }
 }
 
-impl StatefulWidget for PipewireWidget {
+impl StatefulWidget for PipewireWidget<'_> {
     type State = PipewireState;
     fn render(self, area: Rect, buf: &mut Buffer, state: &mut PipewireState) {
-        let icon = match state.muted {
-            true => "󰝟",
-            false => ["󰕿", "󰖀", "󰕾"][(state.volume as usize / 34).clamp(0, 2)],
+        let style = match (state.connected, state.muted) {
+            (false, _) => self.disconnected_style,
+            (true, true) => self.muted_style,
+            (true, false) => Style::new(),
         };
-        Paragraph::new(format!("{} {}", icon, state.volume))
+        Paragraph::new(self.line(state))
+            .style(style)
             .alignment(self.alignment)
             .render(area, buf);
     }
 }
+
+fn average(volumes: &[u8]) -> u8 {
+    match volumes.len() {
+        0 => 0,
+        len => (volumes.iter().map(|volume| *volume as u32).sum::<u32>() / len as u32) as u8,
+    }
+}
+
+/// Names `enum spa_audio_channel` positions the way `pw-dump` does.
+fn channel_position_name(position: u32) -> &'static str {
+    match position {
+        2 => "MONO",
+        3 => "FL",
+        4 => "FR",
+        5 => "FC",
+        6 => "LFE",
+        7 => "SL",
+        8 => "SR",
+        9 => "FLC",
+        10 => "FRC",
+        11 => "RC",
+        12 => "RL",
+        13 => "RR",
+        _ => "?",
+    }
+}
+
+/// Popup listing the output devices, marking the default one.
+pub struct SinksWidget<'a> {
+    state: &'a PipewireState,
+    icons: &'a Icons,
+    highlight_style: Style,
+}
+
+impl<'a> SinksWidget<'a> {
+    pub fn new(state: &'a PipewireState) -> Self {
+        Self {
+            state,
+            icons: Icons::shared(),
+            highlight_style: Theme::default().highlight,
+        }
+    }
+
+    pub fn theme(&mut self, theme: &Theme) {
+        self.highlight_style = theme.highlight;
+    }
+
+    pub fn icons(&mut self, icons: &'a Icons) {
+        self.icons = icons;
+    }
+
+    pub fn height(state: &PipewireState) -> u16 {
+        state.sinks.len().max(1) as u16 + 2
+    }
+}
+
+impl StatefulWidget for SinksWidget<'_> {
+    type State = ListState;
+    fn render(self, area: Rect, buf: &mut Buffer, state: &mut ListState) {
+        let items = match self.state.sinks.is_empty() {
+            true => vec![Line::raw(" No outputs")],
+            false => self
+                .state
+                .sinks
+                .iter()
+                .map(|sink| {
+                    let marker = match sink.name == self.state.default_sink_name() {
+                        true => self.icons.default_sink.as_str(),
+                        false => " ",
+                    };
+                    Line::raw(format!(
+                        "{} {} {}",
+                        marker,
+                        self.icons.sink(sink.kind),
+                        sink.description
+                    ))
+                })
+                .collect(),
+        };
+        Clear.render(area, buf);
+        StatefulWidget::render(
+            List::new(items)
+                .block(Block::bordered().title(" Output "))
+                .highlight_style(self.highlight_style),
+            area,
+            buf,
+            state,
+        );
+    }
+}
+
+/// Popup listing each application stream with its volume, for the mixer.
+pub struct MixerWidget<'a> {
+    state: &'a PipewireState,
+    icons: &'a Icons,
+    highlight_style: Style,
+}
+
+impl<'a> MixerWidget<'a> {
+    pub fn new(state: &'a PipewireState) -> Self {
+        Self {
+            state,
+            icons: Icons::shared(),
+            highlight_style: Theme::default().highlight,
+        }
+    }
+
+    pub fn theme(&mut self, theme: &Theme) {
+        self.highlight_style = theme.highlight;
+    }
+
+    pub fn icons(&mut self, icons: &'a Icons) {
+        self.icons = icons;
+    }
+
+    /// Rows needed to show every stream inside the border.
+    pub fn height(state: &PipewireState) -> u16 {
+        state.streams.len().max(1) as u16 + 2
+    }
+}
+
+impl StatefulWidget for MixerWidget<'_> {
+    type State = ListState;
+    fn render(self, area: Rect, buf: &mut Buffer, state: &mut ListState) {
+        let items = match self.state.streams.is_empty() {
+            true => vec![Line::raw(" Nothing playing")],
+            false => self
+                .state
+                .streams
+                .iter()
+                .map(|stream| {
+                    let icon = match stream.muted {
+                        true => self.icons.muted.as_str(),
+                        false => Icons::level(&self.icons.volume, stream.volume as usize, 34),
+                    };
+                    let name = match stream.media.is_empty() {
+                        true => stream.application.clone(),
+                        false => format!("{}: {}", stream.application, stream.media),
+                    };
+                    Line::raw(format!("{} {:>3} {}", icon, stream.volume, name))
+                })
+                .collect(),
+        };
+        Clear.render(area, buf);
+        StatefulWidget::render(
+            List::new(items)
+                .block(Block::bordered().title(" Mixer "))
+                .highlight_style(self.highlight_style),
+            area,
+            buf,
+            state,
+        );
+    }
+}
+
+/// Popup showing a bar for each channel of the default sink, to spot an unbalanced output.
+pub struct ChannelsWidget<'a> {
+    state: &'a PipewireState,
+    icons: &'a Icons,
+    gauge_style: Style,
+    empty_style: Style,
+}
+
+impl<'a> ChannelsWidget<'a> {
+    pub fn new(state: &'a PipewireState) -> Self {
+        let theme = Theme::default();
+        Self {
+            state,
+            icons: Icons::shared(),
+            gauge_style: theme.gauge,
+            empty_style: theme.separator,
+        }
+    }
+
+    pub fn theme(&mut self, theme: &Theme) {
+        self.gauge_style = theme.gauge;
+        self.empty_style = theme.separator;
+    }
+
+    pub fn icons(&mut self, icons: &'a Icons) {
+        self.icons = icons;
+    }
+
+    pub fn height(state: &PipewireState) -> u16 {
+        state.channel_volumes.len().max(1) as u16 + 2
+    }
+}
+
+impl Widget for ChannelsWidget<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let block = Block::bordered().title(" Channels ");
+        let inner = block.inner(area);
+        Clear.render(area, buf);
+        block.render(area, buf);
+        if self.state.channel_volumes.is_empty() {
+            Paragraph::new(" No channels").render(inner, buf);
+            return;
+        }
+        // Room left for the bar after the channel name and the volume
+        let bar_width = inner.width.saturating_sub(10) as usize;
+        let lines: Vec<Line> = self
+            .state
+            .channel_volumes
+            .iter()
+            .enumerate()
+            .map(|(index, volume)| {
+                let filled = (bar_width * (*volume).min(100) as usize) / 100;
+                Line::from(vec![
+                    Span::raw(format!(" {:<4}", self.state.channel_name(index))),
+                    Span::styled(self.icons.gauge_filled.repeat(filled), self.gauge_style),
+                    Span::styled(
+                        self.icons.gauge_empty.repeat(bar_width - filled),
+                        self.empty_style,
+                    ),
+                    Span::raw(format!(" {:>3}", volume)),
+                ])
+            })
+            .collect();
+        Paragraph::new(lines).render(inner, buf);
+    }
+}
+
+/// Shows which applications are recording the microphone or capturing the screen.
+///
+/// The format is used for each of the two, with the variables `icon` and `apps`.
+pub struct PrivacyWidget<'a> {
+    alignment: Alignment,
+    format: &'a Format,
+    icons: &'a Icons,
+    style: Style,
+}
+
+impl<'a> PrivacyWidget<'a> {
+    pub const FORMAT: &'static str = "{icon} {apps}";
+    pub const VARIABLES: &'static [&'static str] = &["icon", "apps"];
+
+    pub fn new() -> Self {
+        Self {
+            alignment: Alignment::Center,
+            format: &Formats::shared().privacy,
+            icons: Icons::shared(),
+            style: Theme::default().capturing,
+        }
+    }
+
+    pub fn theme(&mut self, theme: &Theme) {
+        self.style = theme.capturing;
+    }
+
+    pub fn format(&mut self, format: &'a Format) {
+        self.format = format;
+    }
+
+    pub fn icons(&mut self, icons: &'a Icons) {
+        self.icons = icons;
+    }
+
+    /// Width needed to list every capturing application, zero when nothing is recording.
+    pub fn width(&self, state: &PipewireState) -> u16 {
+        match self.label(state) {
+            Some(label) => label.width() as u16 + 2,
+            None => 0,
+        }
+    }
+
+    fn label(&self, state: &PipewireState) -> Option<String> {
+        let mut parts = Vec::new();
+        for (kind, icon) in [
+            (CaptureKind::Microphone, &self.icons.microphone),
+            (CaptureKind::Video, &self.icons.camera),
+        ] {
+            let applications = state.capturing(kind);
+            if !applications.is_empty() {
+                let values = Values::new()
+                    .set("icon", icon.as_str())
+                    .set("apps", applications.join(", "));
+                parts.push(self.format.text(&values));
+            }
+        }
+        match parts.is_empty() {
+            true => None,
+            false => Some(parts.join("  ")),
+        }
+    }
+}
+
+impl Default for PrivacyWidget<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StatefulWidget for PrivacyWidget<'_> {
+    type State = PipewireState;
+    fn render(self, area: Rect, buf: &mut Buffer, state: &mut PipewireState) {
+        let Some(label) = self.label(state) else {
+            return;
+        };
+        Paragraph::new(format!(" {} ", label))
+            .style(self.style)
+            .alignment(self.alignment)
+            .render(area, buf);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_default_sink() -> PipewireState {
+        let mut state = PipewireState::new();
+        state.update_sink(AudioSink {
+            id: 40,
+            name: "alsa_output.analog-stereo".to_string(),
+            ..Default::default()
+        });
+        state.update_default("default.audio.sink", Some("alsa_output.analog-stereo".to_string()));
+        state
+    }
+
+    #[test]
+    fn averages_channel_volumes() {
+        let mut state = with_default_sink();
+        state.update_volumes(40, vec![1.0, 0.125]);
+        assert_eq!(state.channel_volumes, vec![100, 50]);
+        assert_eq!(state.volume, 75);
+        assert!(state.unbalanced());
+    }
+
+    #[test]
+    fn changes_stream_volume_keeping_the_balance() {
+        let mut state = PipewireState::new();
+        state.update_stream(52, "mpv".to_string(), "song".to_string(), true);
+        state.update_volumes(52, vec![0.512, 0.125]);
+        let stream = &state.streams[0];
+        assert_eq!(stream.volume, 65);
+
+        let volumes = stream.volumes_changed_by(5);
+        let shown: Vec<u8> = volumes
+            .iter()
+            .map(|volume| (volume.cbrt() * 100.0).round() as u8)
+            .collect();
+        assert_eq!(shown, vec![86, 54]);
+        assert!((volumes[0] / volumes[1] - 0.512 / 0.125).abs() < 1e-4);
+    }
+
+    #[test]
+    fn keeps_stream_volumes_above_100() {
+        let mut state = PipewireState::new();
+        state.update_stream(52, "mpv".to_string(), "song".to_string(), true);
+        assert!(state.streams[0].volumes_changed_by(5).is_empty());
+
+        state.update_volumes(52, vec![1.728, 1.728]);
+        let stream = &state.streams[0];
+        assert_eq!(stream.volume, 120);
+        assert_eq!(stream.volumes_changed_by(5), stream.channel_volumes);
+        assert!(stream.volumes_changed_by(-5)[0] < 1.728);
+    }
+
+    #[test]
+    fn names_channels_from_the_map() {
+        let mut state = with_default_sink();
+        state.update_volumes(40, vec![0.5, 0.5]);
+        state.update_channel_map(40, vec![3, 4]);
+        assert!(!state.unbalanced());
+        assert_eq!(state.channel_name(0), "FL");
+        assert_eq!(state.channel_name(1), "FR");
+        assert_eq!(state.channel_name(2), "3");
+    }
+
+    #[test]
+    fn volumes_seen_before_the_default_are_kept() {
+        let mut state = PipewireState::new();
+        state.update_sink(AudioSink {
+            id: 41,
+            name: "bluez_output.headset".to_string(),
+            ..Default::default()
+        });
+        state.update_volumes(41, vec![0.125, 0.125]);
+        state.update_muted(41, true);
+        state.update_default("default.audio.sink", Some("bluez_output.headset".to_string()));
+        assert_eq!(state.volume, 50);
+        assert!(state.muted);
+    }
+
+    #[test]
+    fn removing_the_default_sink_resets_it() {
+        let mut state = with_default_sink();
+        state.update_volumes(40, vec![1.0, 1.0]);
+        state.remove_node(40);
+        assert!(state.default_sink().is_none());
+        assert_eq!(state.volume, 0);
+        assert!(state.channel_volumes.is_empty());
+        // Volumes for the old id no longer reach the bar
+        state.update_volumes(40, vec![1.0, 1.0]);
+        assert_eq!(state.volume, 0);
+    }
+
+    #[test]
+    fn bluetooth_battery_follows_the_default_sink() {
+        let mut state = PipewireState::new();
+        state.update_sink(AudioSink {
+            id: 41,
+            name: "bluez_output.headset".to_string(),
+            device: Some(39),
+            codec: Some("msbc".to_string()),
+            profile: Some("headset-head-unit".to_string()),
+            ..Default::default()
+        });
+        state.update_device_address(39, Some("AC:80:0A:2B:17:5E".to_string()));
+        state.update_bluetooth_batteries(HashMap::from([("AC:80:0A:2B:17:5E".to_string(), 80)]));
+        assert_eq!(state.default_sink_battery(), None);
+        state.update_default("default.audio.sink", Some("bluez_output.headset".to_string()));
+        assert_eq!(state.default_sink_battery(), Some(80));
+        assert!(state.default_sink().unwrap().headset_profile());
+        state.remove_node(39);
+        assert_eq!(state.default_sink_battery(), None);
+    }
+
+    #[test]
+    fn lists_running_captures_by_kind() {
+        let mut state = PipewireState::new();
+        for (id, application, kind, running) in [
+            (50, "Firefox", CaptureKind::Microphone, true),
+            (51, "Firefox", CaptureKind::Microphone, true),
+            (52, "OBS", CaptureKind::Video, true),
+            (53, "Discord", CaptureKind::Microphone, false),
+        ] {
+            state.update_capture(CaptureStream {
+                id,
+                application: application.to_string(),
+                kind,
+                running,
+            });
+        }
+        assert_eq!(state.capturing(CaptureKind::Microphone), vec!["Firefox"]);
+        assert_eq!(state.capturing(CaptureKind::Video), vec!["OBS"]);
+        state.remove_node(52);
+        assert!(state.capturing(CaptureKind::Video).is_empty());
+    }
+}
@@ -0,0 +1,134 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::time::Instant;
+
+use chrono::{DateTime, FixedOffset, Local, TimeDelta};
+use serde::{Deserialize, Serialize};
+
+use crate::app::AppResult;
+use crate::event::Event;
+
+/// One line of a recording: an event and when it arrived, in milliseconds since recording began.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Recorded {
+    pub at: u64,
+    pub event: Event,
+}
+
+/// The first line of a recording.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Header {
+    /// The wall clock when recording began, the clock is replayed from it.
+    start: DateTime<FixedOffset>,
+}
+
+/// A recording read back.
+#[derive(Debug, Clone)]
+pub struct Recording {
+    pub start: DateTime<FixedOffset>,
+    /// The events in the order they were received.
+    pub events: Vec<Recorded>,
+}
+
+impl Recording {
+    /// The wall clock when `recorded` arrived.
+    pub fn time(&self, recorded: &Recorded) -> DateTime<FixedOffset> {
+        self.start + TimeDelta::milliseconds(recorded.at as i64)
+    }
+}
+
+/// Writes every event the bar receives to a JSON lines file, for `kbar --replay`.
+#[derive(Debug)]
+pub struct Recorder {
+    file: BufWriter<File>,
+    start: Instant,
+}
+
+impl Recorder {
+    pub fn create(path: &str) -> AppResult<Self> {
+        let mut file = BufWriter::new(File::create(path)?);
+        let header = Header {
+            start: Local::now().fixed_offset(),
+        };
+        serde_json::to_writer(&mut file, &header)?;
+        file.write_all(b"\n")?;
+        Ok(Self {
+            file,
+            start: Instant::now(),
+        })
+    }
+
+    /// Appends `event`, flushing straight away so a crash keeps everything up to it.
+    pub fn record(&mut self, event: &Event) -> AppResult<()> {
+        let recorded = Recorded {
+            at: self.start.elapsed().as_millis() as u64,
+            event: event.clone(),
+        };
+        serde_json::to_writer(&mut self.file, &recorded)?;
+        self.file.write_all(b"\n")?;
+        self.file.flush()?;
+        Ok(())
+    }
+}
+
+/// Reads a recording back.
+pub fn read(path: &str) -> AppResult<Recording> {
+    let mut lines = BufReader::new(File::open(path)?).lines();
+    let header: Header = match lines.next() {
+        Some(line) => serde_json::from_str(&line?)?,
+        None => return Err(format!("{}: empty recording", path).into()),
+    };
+    let mut events = Vec::new();
+    for line in lines {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        events.push(serde_json::from_str(&line)?);
+    }
+    Ok(Recording {
+        start: header.start,
+        events,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::Timer;
+    use crate::hyprmon::HyprlandEvent;
+    use crate::pipemon::PipeWireEvent;
+
+    #[test]
+    fn events_survive_a_round_trip() {
+        let events = vec![
+            Event::Tick(Timer::Clock),
+            Event::UpdateHyprlandState(HyprlandEvent::WorkspaceRenamed {
+                id: 3,
+                name: "mail".to_string(),
+            }),
+            Event::UpdatePipeWireState(PipeWireEvent::UpdateVolumes(42, vec![0.5, 0.25])),
+        ];
+        for (at, event) in events.into_iter().enumerate() {
+            let line = serde_json::to_string(&Recorded { at: at as u64, event }).unwrap();
+            let recorded: Recorded = serde_json::from_str(&line).unwrap();
+            assert_eq!(serde_json::to_string(&recorded).unwrap(), line);
+            assert_eq!(recorded.at, at as u64);
+        }
+    }
+
+    #[test]
+    fn replays_the_clock_from_the_start() {
+        let recording = Recording {
+            start: DateTime::parse_from_rfc3339("2025-03-14T09:26:00+01:00").unwrap(),
+            events: vec![Recorded {
+                at: 61_500,
+                event: Event::Tick(Timer::Clock),
+            }],
+        };
+        assert_eq!(
+            recording.time(&recording.events[0]).to_rfc3339(),
+            "2025-03-14T09:27:01.500+01:00"
+        );
+    }
+}
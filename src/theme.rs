@@ -0,0 +1,354 @@
+use std::collections::HashMap;
+
+use ratatui::style::{Color, Modifier, Style};
+use serde::Deserialize;
+
+/// Colors and separators of the bar.
+///
+/// Section styles are the background of each module's area, state styles are patched on top of
+/// them when a module is in that state.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "ThemeConfig")]
+pub struct Theme {
+    /// The whole bar, under every section.
+    pub bar: Style,
+    pub clock: Style,
+    pub workspaces: Style,
+    pub window: Style,
+    pub network: Style,
+    pub privacy: Style,
+    pub submap: Style,
+    pub layout: Style,
+    pub volume: Style,
+    pub battery: Style,
+    pub workspace_active: Style,
+    pub workspace_empty: Style,
+    /// Workspaces with a window asking for attention.
+    pub workspace_urgent: Style,
+    pub muted: Style,
+    pub charging: Style,
+    /// A battery running out.
+    pub critical: Style,
+    /// Hyprland, PipeWire or the wifi being gone.
+    pub disconnected: Style,
+    /// A Bluetooth headset that fell back to its headset profile.
+    pub warning: Style,
+    /// Apps using the microphone or camera.
+    pub capturing: Style,
+    /// The selected row of a popup.
+    pub highlight: Style,
+    /// The filled part of the channel bars.
+    pub gauge: Style,
+    /// Separators, and the dimmed parts of popups: week numbers and the empty part of gauges.
+    pub separator: Style,
+    /// Drawn between the sections left of the window title, e.g. "" for powerline.
+    pub separator_left: String,
+    /// Drawn between the sections right of the window title, e.g. "".
+    pub separator_right: String,
+    /// Colors separators from the backgrounds of the sections they join, instead of `separator`.
+    pub powerline: bool,
+}
+
+impl Default for Theme {
+    /// The terminal's own colors, with the few highlights kbar always had.
+    fn default() -> Self {
+        Self {
+            bar: Style::new(),
+            clock: Style::new(),
+            workspaces: Style::new(),
+            window: Style::new(),
+            network: Style::new(),
+            privacy: Style::new(),
+            submap: Style::new().fg(Color::Black).bg(Color::Yellow),
+            layout: Style::new(),
+            volume: Style::new(),
+            battery: Style::new(),
+            workspace_active: Style::new().fg(Color::Black).bg(Color::Blue),
+            workspace_empty: Style::new().add_modifier(Modifier::DIM),
+            workspace_urgent: Style::new().fg(Color::Red).add_modifier(Modifier::BOLD),
+            muted: Style::new(),
+            charging: Style::new(),
+            critical: Style::new().fg(Color::Black).bg(Color::Red),
+            disconnected: Style::new().fg(Color::DarkGray),
+            warning: Style::new().fg(Color::Yellow),
+            capturing: Style::new()
+                .fg(Color::White)
+                .bg(Color::Red)
+                .add_modifier(Modifier::BOLD),
+            highlight: Style::new().fg(Color::Black).bg(Color::Blue),
+            gauge: Style::new().fg(Color::Blue),
+            separator: Style::new().fg(Color::DarkGray),
+            separator_left: "".to_string(),
+            separator_right: "".to_string(),
+            powerline: false,
+        }
+    }
+}
+
+/// The colors a built-in theme is made from.
+struct Palette {
+    base: Color,
+    surface: Color,
+    overlay: Color,
+    subtle: Color,
+    text: Color,
+    accent: Color,
+    red: Color,
+    yellow: Color,
+    green: Color,
+}
+
+const CATPPUCCIN: Palette = Palette {
+    base: Color::from_u32(0x1e1e2e),
+    surface: Color::from_u32(0x313244),
+    overlay: Color::from_u32(0x45475a),
+    subtle: Color::from_u32(0x6c7086),
+    text: Color::from_u32(0xcdd6f4),
+    accent: Color::from_u32(0x89b4fa),
+    red: Color::from_u32(0xf38ba8),
+    yellow: Color::from_u32(0xf9e2af),
+    green: Color::from_u32(0xa6e3a1),
+};
+
+const GRUVBOX: Palette = Palette {
+    base: Color::from_u32(0x282828),
+    surface: Color::from_u32(0x3c3836),
+    overlay: Color::from_u32(0x504945),
+    subtle: Color::from_u32(0x928374),
+    text: Color::from_u32(0xebdbb2),
+    accent: Color::from_u32(0x83a598),
+    red: Color::from_u32(0xfb4934),
+    yellow: Color::from_u32(0xfabd2f),
+    green: Color::from_u32(0xb8bb26),
+};
+
+const NORD: Palette = Palette {
+    base: Color::from_u32(0x2e3440),
+    surface: Color::from_u32(0x3b4252),
+    overlay: Color::from_u32(0x434c5e),
+    subtle: Color::from_u32(0x4c566a),
+    text: Color::from_u32(0xeceff4),
+    accent: Color::from_u32(0x88c0d0),
+    red: Color::from_u32(0xbf616a),
+    yellow: Color::from_u32(0xebcb8b),
+    green: Color::from_u32(0xa3be8c),
+};
+
+impl Theme {
+    fn from_palette(palette: &Palette) -> Self {
+        let on = |color: Color| Style::new().fg(palette.base).bg(color);
+        Self {
+            bar: Style::new().fg(palette.text).bg(palette.base),
+            clock: Style::new().bg(palette.surface),
+            workspaces: Style::new(),
+            window: Style::new(),
+            network: Style::new().bg(palette.surface),
+            privacy: Style::new(),
+            submap: on(palette.yellow),
+            layout: Style::new().bg(palette.overlay),
+            volume: Style::new().bg(palette.surface),
+            battery: Style::new().bg(palette.overlay),
+            workspace_active: on(palette.accent),
+            workspace_empty: Style::new().fg(palette.subtle),
+            workspace_urgent: on(palette.red),
+            muted: Style::new().fg(palette.subtle),
+            charging: Style::new().fg(palette.green),
+            critical: on(palette.red),
+            disconnected: Style::new().fg(palette.subtle),
+            warning: Style::new().fg(palette.yellow),
+            capturing: on(palette.red).add_modifier(Modifier::BOLD),
+            highlight: on(palette.accent),
+            gauge: Style::new().fg(palette.accent),
+            separator: Style::new().fg(palette.subtle),
+            ..Self::default()
+        }
+    }
+
+    /// Builds the theme a config's `[theme]` section describes.
+    pub fn from_config(config: &ThemeConfig) -> Result<Self, String> {
+        let mut theme = match config.base {
+            Base::Default => Self::default(),
+            Base::Catppuccin => Self::from_palette(&CATPPUCCIN),
+            Base::Gruvbox => Self::from_palette(&GRUVBOX),
+            Base::Nord => Self::from_palette(&NORD),
+        };
+        if let Some(separator) = &config.separator_left {
+            theme.separator_left = separator.clone();
+        }
+        if let Some(separator) = &config.separator_right {
+            theme.separator_right = separator.clone();
+        }
+        if let Some(powerline) = config.powerline {
+            theme.powerline = powerline;
+        }
+        for (name, style) in &config.styles {
+            let Some(themed) = theme.style_mut(name) else {
+                return Err(format!("unknown style \"{}\"", name));
+            };
+            *themed = themed.patch(style.style());
+        }
+        Ok(theme)
+    }
+
+    fn style_mut(&mut self, name: &str) -> Option<&mut Style> {
+        Some(match name {
+            "bar" => &mut self.bar,
+            "clock" => &mut self.clock,
+            "workspaces" => &mut self.workspaces,
+            "window" => &mut self.window,
+            "network" => &mut self.network,
+            "privacy" => &mut self.privacy,
+            "submap" => &mut self.submap,
+            "layout" => &mut self.layout,
+            "volume" => &mut self.volume,
+            "battery" => &mut self.battery,
+            "workspace_active" => &mut self.workspace_active,
+            "workspace_empty" => &mut self.workspace_empty,
+            "workspace_urgent" => &mut self.workspace_urgent,
+            "muted" => &mut self.muted,
+            "charging" => &mut self.charging,
+            "critical" => &mut self.critical,
+            "disconnected" => &mut self.disconnected,
+            "warning" => &mut self.warning,
+            "capturing" => &mut self.capturing,
+            "highlight" => &mut self.highlight,
+            "gauge" => &mut self.gauge,
+            "separator" => &mut self.separator,
+            _ => return None,
+        })
+    }
+}
+
+impl TryFrom<ThemeConfig> for Theme {
+    type Error = String;
+
+    fn try_from(config: ThemeConfig) -> Result<Self, String> {
+        Self::from_config(&config)
+    }
+}
+
+/// The `[theme]` section of the config file.
+///
+/// ```toml
+/// [theme]
+/// base = "nord"
+/// separator_right = ""
+/// powerline = true
+///
+/// [theme.styles]
+/// volume = { fg = "black", bg = "#88c0d0" }
+/// muted = { modifiers = ["crossed_out"] }
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ThemeConfig {
+    pub base: Base,
+    pub separator_left: Option<String>,
+    pub separator_right: Option<String>,
+    pub powerline: Option<bool>,
+    /// Patches the base theme's styles, keyed by the names of `Theme`'s style fields.
+    pub styles: HashMap<String, StyleConfig>,
+}
+
+/// Built-in themes.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Base {
+    #[default]
+    Default,
+    /// Catppuccin Mocha.
+    Catppuccin,
+    /// Gruvbox dark.
+    Gruvbox,
+    Nord,
+}
+
+/// A style in the config file. Colors are names like "blue", indices like "42" or "#rrggbb".
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StyleConfig {
+    pub fg: Option<Color>,
+    pub bg: Option<Color>,
+    pub modifiers: Vec<StyleModifier>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StyleModifier {
+    Bold,
+    Dim,
+    Italic,
+    Underlined,
+    SlowBlink,
+    RapidBlink,
+    Reversed,
+    Hidden,
+    CrossedOut,
+}
+
+impl StyleConfig {
+    fn style(&self) -> Style {
+        let modifier = self
+            .modifiers
+            .iter()
+            .fold(Modifier::empty(), |modifier, added| {
+                modifier
+                    | match added {
+                        StyleModifier::Bold => Modifier::BOLD,
+                        StyleModifier::Dim => Modifier::DIM,
+                        StyleModifier::Italic => Modifier::ITALIC,
+                        StyleModifier::Underlined => Modifier::UNDERLINED,
+                        StyleModifier::SlowBlink => Modifier::SLOW_BLINK,
+                        StyleModifier::RapidBlink => Modifier::RAPID_BLINK,
+                        StyleModifier::Reversed => Modifier::REVERSED,
+                        StyleModifier::Hidden => Modifier::HIDDEN,
+                        StyleModifier::CrossedOut => Modifier::CROSSED_OUT,
+                    }
+            });
+        Style {
+            fg: self.fg,
+            bg: self.bg,
+            add_modifier: modifier,
+            ..Style::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn theme(config: &str) -> Result<Theme, toml::de::Error> {
+        toml::from_str(config)
+    }
+
+    #[test]
+    fn patches_the_base_theme() {
+        let theme = theme(
+            r##"
+            base = "nord"
+            separator_right = ""
+            powerline = true
+
+            [styles]
+            volume = { fg = "black", bg = "#112233" }
+            muted = { modifiers = ["crossed_out"] }
+            "##,
+        )
+        .unwrap();
+        assert_eq!(theme.volume, Style::new().fg(Color::Black).bg(Color::Rgb(0x11, 0x22, 0x33)));
+        assert_eq!(
+            theme.muted,
+            Style::new().fg(NORD.subtle).add_modifier(Modifier::CROSSED_OUT)
+        );
+        assert_eq!(theme.bar.bg, Some(NORD.base));
+        assert_eq!(theme.separator_right, "");
+        assert!(theme.powerline);
+    }
+
+    #[test]
+    fn rejects_unknown_styles() {
+        assert!(theme("[styles]\nvolumes = { fg = \"red\" }").is_err());
+        assert!(theme("base = \"solarized\"").is_err());
+    }
+}
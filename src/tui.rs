@@ -18,12 +18,21 @@ pub struct Tui<B: Backend> {
     terminal: Terminal<B>,
     /// Terminal event handler.
     pub events: EventHandler,
+    /// Lines the terminal had before `fit` grew it.
+    rows_before: Option<u16>,
+    /// Lines `fit` was last asked for.
+    rows_requested: Option<u16>,
 }
 
 impl<B: Backend> Tui<B> {
     /// Constructs a new instance of [`Tui`].
     pub fn new(terminal: Terminal<B>, events: EventHandler) -> Self {
-        Self { terminal, events }
+        Self {
+            terminal,
+            events,
+            rows_before: None,
+            rows_requested: None,
+        }
     }
 
     /// Initializes the terminal interface.
@@ -55,6 +64,32 @@ impl<B: Backend> Tui<B> {
         Ok(())
     }
 
+    /// Grows the terminal to `rows` lines while a popup needs them, and shrinks it back to its
+    /// size from before once none does.
+    ///
+    /// This asks the terminal to resize its window, once each time the lines needed change. Most
+    /// Wayland terminals ignore it and keep their size, the popups are then cut to it and lists
+    /// scroll to keep their selected row in view.
+    pub fn fit(&mut self, rows: Option<u16>) -> AppResult<()> {
+        if rows == self.rows_requested {
+            return Ok(());
+        }
+        self.rows_requested = rows;
+        let (columns, current) = terminal::size()?;
+        match (rows, self.rows_before) {
+            (Some(rows), _) if rows > current => {
+                self.rows_before.get_or_insert(current);
+                crossterm::execute!(io::stdout(), terminal::SetSize(columns, rows))?;
+            }
+            (None, Some(before)) => {
+                self.rows_before = None;
+                crossterm::execute!(io::stdout(), terminal::SetSize(columns, before))?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
     /// Resets the terminal interface.
     ///
     /// This function is also used for the panic hook to revert
@@ -69,6 +104,7 @@ impl<B: Backend> Tui<B> {
     ///
     /// It disables the raw mode and reverts back the terminal properties.
     pub fn exit(&mut self) -> AppResult<()> {
+        self.fit(None)?;
         Self::reset()?;
         self.terminal.show_cursor()?;
         Ok(())
@@ -1,4 +1,3 @@
-use chrono;
 use ratatui::{
     Frame,
     layout::{Rect, Constraint, Layout, Position, Flex},
@@ -8,37 +7,485 @@ use ratatui::{
 
 };
 
-use crate::{app::App, networkwidget::NetworkWidget};
-use crate::hyprlandwidget::{HyprlandWorkSpaceWidget};
+use unicode_width::UnicodeWidthStr;
+
+use crate::{
+    app::App,
+    networkwidget::NetworkWidget,
+    overlay::Popup,
+    theme::Theme,
+};
+use crate::hyprlandwidget::{
+    HyprlandLayoutWidget, HyprlandSubmapWidget, HyprlandWindowWidget, HyprlandWorkSpaceWidget,
+};
 use crate::batterywidget::BatteryWidget;
-use crate::pipewirewidget::PipewireWidget;
+use crate::clockwidget::{CalendarWidget, ClockWidget};
+use crate::pipewirewidget::{
+    ChannelsWidget, MixerWidget, PipewireWidget, PrivacyWidget, SinksWidget,
+};
 
 pub fn render(app: &mut App, frame: &mut Frame) {
-    let layout= Layout::horizontal([Constraint::Ratio(1,3), Constraint::Ratio(1,3), Constraint::Ratio(1,3)]).split(frame.area());
-    let left = Layout::horizontal([Constraint::Min(17), Constraint::Percentage(100)]).split(layout[0]);
-    let right = Layout::horizontal([Constraint::Percentage(100), Constraint:: Min(7), Constraint::Min(7)]).split(layout[2]);
-    let clock = Paragraph::new(format!(
-        "{}",
-        chrono::offset::Local::now().format("%a %b %d %H:%M")
-    ));
+    let theme = app.theme.clone();
+    let icons = &app.icons;
+    let formats = &app.formats;
+    // The bar is the top line, the lines below it are for popups
+    let area = Rect { height: 1.min(frame.area().height), ..frame.area() };
+    frame.buffer_mut().set_style(area, theme.bar);
+    let clock = ClockWidget::new(&app.clock, app.now());
+    let mut workspaces = HyprlandWorkSpaceWidget::new();
+    workspaces.format(&formats.workspaces);
+    workspaces.theme(&theme);
+    workspaces.icons(icons);
+    let mut submap = HyprlandSubmapWidget::new();
+    submap.theme(&theme);
+    submap.format(&formats.submap);
+    let mut privacy = PrivacyWidget::new();
+    privacy.theme(&theme);
+    privacy.icons(icons);
+    privacy.format(&formats.privacy);
+    let mut keyboard = HyprlandLayoutWidget::new();
+    keyboard.format(&formats.layout);
+    let mut network = NetworkWidget::new();
+    network.right_aligned();
+    network.theme(&theme);
+    network.icons(icons);
+    network.format(&formats.network);
+    let mut pipewire = PipewireWidget::new();
+    pipewire.center_aligned();
+    pipewire.theme(&theme);
+    pipewire.icons(icons);
+    pipewire.format(&formats.volume);
+    let mut battery = BatteryWidget::new();
+    battery.right_aligned();
+    battery.theme(&theme);
+    battery.icons(icons);
+    battery.format(&formats.battery);
+
+    let padded = |width: u16| width + 2;
+    let mut right_widths = [
+        padded(network.width(&app.network_state)),
+        privacy.width(&app.pipwire_state),
+        submap.width(&app.hyprland_state),
+        padded(keyboard.width(&app.hyprland_state)),
+        padded(pipewire.width(&app.pipwire_state)),
+        padded(battery.width(&app.battery_state)),
+    ];
+    let separator_left = theme.separator_left.width() as u16;
+    let separator_right = theme.separator_right.width() as u16;
+    let clock_width = clock.width() + 1;
+    let left_min = total_width(&[clock_width, workspaces.min_width(&app.hyprland_state)], separator_left);
+    let left_full = total_width(&[clock_width, workspaces.width(&app.hyprland_state)], separator_left);
+    // Short of room the window title shrinks first, then the workspace tabs down to the active one,
+    // then the right modules are hidden by `RIGHT_DROP_ORDER`
+    for index in RIGHT_DROP_ORDER {
+        if left_min + total_width(&right_widths, separator_right) <= area.width {
+            break;
+        }
+        right_widths[index] = 0;
+    }
+    let right_width = total_width(&right_widths, separator_right);
+    // Equal sides keep the window title centered on the bar while there is room for it
+    let side = left_full.max(right_width);
+    let (left_width, right_width) = match 2 * side <= area.width {
+        true => (side, side),
+        false => (left_full.min(area.width.saturating_sub(right_width)), right_width),
+    };
+    let layout = Layout::horizontal([
+        Constraint::Length(left_width),
+        Constraint::Fill(1),
+        Constraint::Length(right_width),
+    ])
+    .split(area);
+    let left = sections(frame, &theme, Side::Left, layout[0], &[(Constraint::Length(clock_width), theme.clock), (Constraint::Fill(1), theme.workspaces)]);
+    frame.buffer_mut().set_style(layout[1], theme.window);
+    let section = |index: usize| match (index, right_widths[index]) {
+        (_, 0) => Constraint::Length(0),
+        // The network takes whatever room the other modules leave
+        (0, _) => Constraint::Fill(1),
+        (_, width) => Constraint::Length(width),
+    };
+    let right = sections(frame, &theme, Side::Right, layout[2], &[
+        (section(0), theme.network),
+        (section(1), theme.privacy),
+        (section(2), theme.submap),
+        (section(3), theme.layout),
+        (section(4), theme.volume),
+        (section(5), theme.battery),
+    ]);
     frame.render_widget(clock, left[0]);
-    let workspaces = HyprlandWorkSpaceWidget::new();
+    app.clock_area = left[0];
     let mut hyprstate = app.hyprland_state.clone();
     frame.render_stateful_widget(workspaces, left[1], &mut hyprstate);
 
-    let activewindow = Paragraph::new(app.hyprland_state.activewindow.clone()).centered();
-    frame.render_widget(activewindow, layout[1]);
+    let mut activewindow = HyprlandWindowWidget::new();
+    activewindow.marquee();
+    activewindow.icons(icons);
+    activewindow.format(&formats.window);
+    app.title_scrolling = activewindow.scrolling(&app.hyprland_state, layout[1].width);
+    frame.render_stateful_widget(activewindow, layout[1], &mut hyprstate);
+
+    frame.render_stateful_widget(submap, right[2], &mut hyprstate);
+    frame.render_stateful_widget(keyboard, right[3], &mut hyprstate);
 
     let mut networkstate = app.network_state.clone();
-    let mut network = NetworkWidget::new();
-    network.right_aligned();
     frame.render_stateful_widget(network, right[0], &mut networkstate);
     let mut pwstate = app.pipwire_state.clone();
-    let mut pipewire = PipewireWidget::new();
-    pipewire.center_aligned();
-    frame.render_stateful_widget(pipewire, right[1], &mut pwstate);
-    let mut battery = BatteryWidget::new();
-    battery.right_aligned();
+    frame.render_stateful_widget(pipewire, right[4], &mut pwstate);
+    frame.render_stateful_widget(privacy, right[1], &mut pwstate);
+    app.volume_area = right[4];
     let mut batstate = app.battery_state.clone();
-    frame.render_stateful_widget(battery, right[2], &mut batstate);
+    frame.render_stateful_widget(battery, right[5], &mut batstate);
+
+    // Popups hang below the bar, the terminal is grown to fit them after drawing
+    let Some(popup) = app.overlay.as_ref().map(|overlay| overlay.popup) else {
+        return;
+    };
+    let anchor = app.anchor(popup);
+    let today = app.now().date_naive();
+    let (width, height) = match popup {
+        Popup::Mixer => (48, MixerWidget::height(&app.pipwire_state)),
+        Popup::Sinks => (48, SinksWidget::height(&app.pipwire_state)),
+        Popup::Channels => (48, ChannelsWidget::height(&app.pipwire_state)),
+        Popup::Calendar => (CalendarWidget::WIDTH, CalendarWidget::height(app.calendar)),
+    };
+    let Some(overlay) = &mut app.overlay else {
+        return;
+    };
+    overlay.anchor = anchor;
+    let area = overlay.place(width, height, frame.area());
+    if area.height <= 2 {
+        // Nothing is drawn, so clicks there dismiss the popup like anywhere else
+        overlay.area = Rect::default();
+        return;
+    }
+    match popup {
+        Popup::Mixer => {
+            let mut mixer = MixerWidget::new(&app.pipwire_state);
+            mixer.theme(&theme);
+            mixer.icons(icons);
+            frame.render_stateful_widget(mixer, area, &mut overlay.list)
+        }
+        Popup::Sinks => {
+            let mut sinks = SinksWidget::new(&app.pipwire_state);
+            sinks.theme(&theme);
+            sinks.icons(icons);
+            frame.render_stateful_widget(sinks, area, &mut overlay.list)
+        }
+        Popup::Channels => {
+            let mut channels = ChannelsWidget::new(&app.pipwire_state);
+            channels.theme(&theme);
+            channels.icons(icons);
+            frame.render_widget(channels, area)
+        }
+        Popup::Calendar => {
+            let mut calendar = CalendarWidget::new(app.calendar, today);
+            calendar.theme(&theme);
+            frame.render_widget(calendar, area)
+        }
+    }
+}
+
+/// The order right modules are hidden in when the bar is too narrow for all of them, by index:
+/// the network first, the battery last.
+const RIGHT_DROP_ORDER: [usize; 6] = [0, 3, 2, 1, 4, 5];
+
+/// Width of sections `widths` wide side by side, with a separator between the shown ones.
+fn total_width(widths: &[u16], separator: u16) -> u16 {
+    let shown = widths.iter().filter(|&&width| width > 0).count() as u16;
+    widths.iter().sum::<u16>() + separator * shown.saturating_sub(1)
+}
+
+/// Which side of the window title a group of sections is on, separators point away from it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Side {
+    Left,
+    Right,
+}
+
+/// Splits `area` between `sections`, filling each with its background, with the theme's separator
+/// between the ones that are shown. Hidden sections, `Length(0)`, get an empty area.
+fn sections(
+    frame: &mut Frame,
+    theme: &Theme,
+    side: Side,
+    area: Rect,
+    sections: &[(Constraint, Style)],
+) -> Vec<Rect> {
+    let separator = match side {
+        Side::Left => &theme.separator_left,
+        Side::Right => &theme.separator_right,
+    };
+    let separator_width = separator.width() as u16;
+    let shown: Vec<usize> = (0..sections.len())
+        .filter(|&index| sections[index].0 != Constraint::Length(0))
+        .collect();
+    let mut constraints = Vec::new();
+    for (n, &index) in shown.iter().enumerate() {
+        if n > 0 && separator_width > 0 {
+            constraints.push(Constraint::Length(separator_width));
+        }
+        constraints.push(sections[index].0);
+    }
+    let areas = Layout::horizontal(constraints).split(area);
+    let step = if separator_width > 0 { 2 } else { 1 };
+    let mut placed = vec![Rect::default(); sections.len()];
+    for (n, &index) in shown.iter().enumerate() {
+        let style = sections[index].1;
+        placed[index] = areas[n * step];
+        frame.buffer_mut().set_style(placed[index], style);
+        if n == 0 || separator_width == 0 {
+            continue;
+        }
+        let previous = sections[shown[n - 1]].1;
+        let background = |section: Style| section.bg.or(theme.bar.bg);
+        // A powerline arrow takes the color of the section it points out of
+        let separator_style = match (theme.powerline, side) {
+            (false, _) => theme.separator,
+            (true, Side::Left) => Style { fg: background(previous), bg: background(style), ..Style::default() },
+            (true, Side::Right) => Style { fg: background(style), bg: background(previous), ..Style::default() },
+        };
+        frame.render_widget(
+            Paragraph::new(separator.as_str()).style(separator_style),
+            areas[n * step - 1],
+        );
+    }
+    placed
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use chrono::DateTime;
+    use hyprland::shared::Address;
+    use ratatui::{Terminal, backend::TestBackend, buffer::Buffer};
+
+    use super::*;
+    use crate::batterywidget::BatteryChargingState;
+    use crate::hyprlandwidget::{HyprlandClient, HyprlandWorkspace};
+    use crate::networkwidget::Connection;
+    use crate::overlay::Overlay;
+    use crate::pipewirewidget::{AudioSink, CaptureKind, CaptureStream, PipewireState};
+
+    const WIDTHS: [u16; 3] = [80, 120, 200];
+
+    /// A bar with everything connected, one workspace and a focused terminal.
+    fn app() -> App {
+        let mut app = App::offline();
+        app.now = DateTime::parse_from_rfc3339("2025-03-14T09:26:00+01:00").ok();
+        app.hyprland_state.connected = true;
+        app.hyprland_state.workspaces = vec![workspace(1)];
+        app.hyprland_state.activeworkspace = 1;
+        app.hyprland_state.clients = vec![client(1, "kitty")];
+        app.hyprland_state.activewindow.address = Some(Address::new("0x1"));
+        app.hyprland_state.activewindow.class = "kitty".to_string();
+        app.hyprland_state.activewindow.title = "~/src/kbar".to_string();
+        app.hyprland_state.keyboard_layout = "us".to_string();
+        app.battery_state.capacity = 82;
+        app.network_state.state = Connection::Connected;
+        app.network_state.ssid = "home".to_string();
+        app.network_state.signal_dbm = Some(-68);
+        let pipewire = &mut app.pipwire_state;
+        pipewire.connected = true;
+        pipewire.update_sink(AudioSink {
+            id: 40,
+            name: "alsa_output.analog-stereo".to_string(),
+            description: "Built-in Audio".to_string(),
+            ..Default::default()
+        });
+        pipewire.update_default("default.audio.sink", Some("alsa_output.analog-stereo".to_string()));
+        pipewire.update_volumes(40, vec![0.343, 0.343]);
+        app
+    }
+
+    fn workspace(id: i32) -> HyprlandWorkspace {
+        HyprlandWorkspace {
+            id,
+            name: id.to_string(),
+            monitor: "eDP-1".to_string(),
+        }
+    }
+
+    fn client(workspace: i32, class: &str) -> HyprlandClient {
+        HyprlandClient {
+            address: Address::new(format!("0x{}", workspace)),
+            workspace,
+            class: class.to_string(),
+            fullscreen: false,
+            floating: false,
+            pinned: false,
+            urgent: false,
+        }
+    }
+
+    /// The runs of bar cells sharing a style, one per line with their first and last column.
+    fn styles(buffer: &Buffer, width: u16) -> String {
+        let style = |x: u16| {
+            let cell = &buffer[(x, 0)];
+            (cell.fg, cell.bg, cell.modifier)
+        };
+        let mut runs = String::new();
+        let mut start = 0;
+        for x in 1..=width {
+            if x < width && style(x) == style(start) {
+                continue;
+            }
+            let (fg, bg, modifier) = style(start);
+            runs.push_str(&format!(
+                "    {:>3}-{:<3} fg {:?} bg {:?} {:?}\n",
+                start,
+                x - 1,
+                fg,
+                bg,
+                modifier
+            ));
+            start = x;
+        }
+        runs
+    }
+
+    /// Draws the bar at each of `WIDTHS`, with its styles, and compares it with `src/snapshots/{name}.txt`.
+    ///
+    /// Run with `KBAR_UPDATE_SNAPSHOTS=1` to write the snapshots instead, after checking the change is wanted.
+    fn assert_snapshot(name: &str, mut app: App) {
+        let mut rendered = String::new();
+        for width in WIDTHS {
+            let mut terminal = Terminal::new(TestBackend::new(width, 1)).unwrap();
+            terminal.draw(|frame| render(&mut app, frame)).unwrap();
+            let buffer = terminal.backend().buffer();
+            let line: String = (0..width).map(|x| buffer[(x, 0)].symbol()).collect();
+            rendered.push_str(&format!("{:>3}|{}|\n", width, line));
+            rendered.push_str(&styles(buffer, width));
+        }
+        let path = format!("{}/src/snapshots/{}.txt", env!("CARGO_MANIFEST_DIR"), name);
+        if env::var_os("KBAR_UPDATE_SNAPSHOTS").is_some() {
+            fs::write(&path, &rendered).unwrap();
+            return;
+        }
+        let expected = fs::read_to_string(&path).unwrap_or_default();
+        assert!(
+            rendered == expected,
+            "{} differs from {}:\n{}",
+            name,
+            path,
+            rendered
+        );
+    }
+
+    #[test]
+    fn connected() {
+        assert_snapshot("connected", app());
+    }
+
+    #[test]
+    fn disconnected() {
+        let mut app = app();
+        app.hyprland_state.connected = false;
+        app.network_state.disconnected();
+        app.pipwire_state = PipewireState::new();
+        assert_snapshot("disconnected", app);
+    }
+
+    #[test]
+    fn muted() {
+        let mut app = app();
+        app.pipwire_state.update_muted(40, true);
+        assert_snapshot("muted", app);
+    }
+
+    #[test]
+    fn charging() {
+        let mut app = app();
+        app.battery_state.state = BatteryChargingState::Charging;
+        app.battery_state.capacity = 100;
+        assert_snapshot("charging", app);
+    }
+
+    #[test]
+    fn ten_workspaces() {
+        let mut app = app();
+        let hyprland = &mut app.hyprland_state;
+        hyprland.workspaces = (1..=10).map(workspace).collect();
+        hyprland.clients = (1..=10)
+            .step_by(3)
+            .map(|id| client(id, ["kitty", "firefox"][id as usize % 2]))
+            .collect();
+        hyprland.activeworkspace = 7;
+        assert_snapshot("ten_workspaces", app);
+    }
+
+    #[test]
+    fn long_title() {
+        let mut app = app();
+        app.hyprland_state.activewindow.class = "firefox".to_string();
+        app.hyprland_state.activewindow.title =
+            "Pull request #1234: Draw only when state changed and give each module its own timer — Mozilla Firefox"
+                .to_string();
+        assert_snapshot("long_title", app);
+    }
+
+    #[test]
+    fn submap_and_capture() {
+        let mut app = app();
+        app.hyprland_state.submap = "resize".to_string();
+        app.pipwire_state.update_capture(CaptureStream {
+            id: 77,
+            application: "zoom".to_string(),
+            kind: CaptureKind::Microphone,
+            running: true,
+        });
+        assert_snapshot("submap_and_capture", app);
+    }
+
+    #[test]
+    fn powerline_theme() {
+        let mut app = app();
+        app.theme = toml::from_str(
+            "base = \"nord\"\nseparator_left = \"\u{e0b0}\"\nseparator_right = \"\u{e0b2}\"\npowerline = true",
+        )
+        .unwrap();
+        app.hyprland_state.submap = "resize".to_string();
+        assert_snapshot("powerline_theme", app);
+    }
+
+    #[test]
+    fn ascii_icons() {
+        let mut app = app();
+        app.icons = crate::icons::Icons::ascii();
+        assert_snapshot("ascii_icons", app);
+    }
+
+    #[test]
+    fn timezones() {
+        let mut app = app();
+        app.clock = toml::from_str(
+            "format = \"%H:%M\"\ntimezones = [{ label = \"NYC\", timezone = \"America/New_York\" }]",
+        )
+        .unwrap();
+        assert_snapshot("timezones", app);
+    }
+
+    #[test]
+    fn calendar_under_the_clock() {
+        let mut app = app();
+        app.overlay = Some(Overlay::new(Popup::Calendar, Rect::default()));
+        app.calendar = app.now().date_naive();
+        let mut terminal = Terminal::new(TestBackend::new(80, 10)).unwrap();
+        terminal.draw(|frame| render(&mut app, frame)).unwrap();
+        let buffer = terminal.backend().buffer();
+        let row = |y: u16| -> String { (0..26).map(|x| buffer[(x, y)].symbol()).collect() };
+        assert_eq!(row(1), "┌ March 2025 ────────────┐");
+        assert_eq!(row(3), "│  9                 1  2│");
+        assert_eq!(row(5), "│ 11 10 11 12 13 14 15 16│");
+        assert_eq!(buffer[(17, 5)].style().bg, Theme::default().highlight.bg);
+    }
+
+    #[test]
+    fn leaves_no_area_for_a_cut_popup() {
+        let mut app = app();
+        app.overlay = Some(Overlay::new(Popup::Calendar, Rect::default()));
+        let mut terminal = Terminal::new(TestBackend::new(80, 2)).unwrap();
+        terminal.draw(|frame| render(&mut app, frame)).unwrap();
+        assert_eq!(app.overlay.unwrap().area, Rect::default());
+    }
 }